@@ -0,0 +1,230 @@
+use crate::constants::{DEFAULT_IN_FLIGHT, DEFAULT_RPC_URL, DEFAULT_TIMEOUT_SECS};
+use crate::errors::{MetabobError, Result};
+use crate::opt::Opt;
+use crate::parse::SolanaConfig;
+use crate::rpc::EndpointConfig;
+use log::info;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::{
+    collections::HashMap,
+    env, fs,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+/// One named set of defaults in the metabob config file. Anything left out falls through
+/// to the Solana CLI config or the built in defaults
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub rpc: Option<String>,
+    pub heavy_rpc: Option<String>,
+    /// Extra endpoints, same format as --endpoint
+    pub endpoints: Vec<String>,
+    pub commitment: Option<String>,
+    pub keypair: Option<String>,
+    pub rps: Option<NonZeroU32>,
+    pub in_flight: Option<usize>,
+    pub timeout: Option<u64>,
+    pub output_dir: Option<String>,
+    pub db: Option<String>,
+    /// Priority fee in micro-lamports per compute unit
+    pub priority_fee: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetabobConfig {
+    pub default_profile: Option<String>,
+    pub profiles: HashMap<String, Profile>,
+}
+
+impl MetabobConfig {
+    pub fn load(path: &Path) -> Result<MetabobConfig> {
+        load_config_file(path, "metabob config")
+    }
+
+    /// The named profile, or the default profile if no name is given
+    pub fn profile(&self, name: Option<&str>) -> Result<Option<Profile>> {
+        match name.or(self.default_profile.as_deref()) {
+            Some(name) => self.profiles.get(name).cloned().map(Some).ok_or_else(|| {
+                MetabobError::config(format!("No profile named {} in the metabob config", name))
+            }),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Everything metabob needs to know before running a command, after applying
+/// CLI > env > profile > Solana CLI config precedence
+#[derive(Debug)]
+pub struct Settings {
+    pub profile: Option<String>,
+    pub rpc: String,
+    pub heavy_rpc: Option<String>,
+    pub endpoints: Vec<EndpointConfig>,
+    pub commitment: CommitmentConfig,
+    pub keypair: Option<String>,
+    pub rps: Option<NonZeroU32>,
+    pub in_flight: usize,
+    pub timeout: Duration,
+    pub output_dir: PathBuf,
+    pub db: Option<PathBuf>,
+    pub priority_fee: Option<u64>,
+}
+
+impl Settings {
+    /// CLI flags and env vars both arrive through `options`, structopt falls back to the env
+    pub fn resolve(options: &Opt, solana_config: Option<SolanaConfig>) -> Result<Settings> {
+        let config_path = options
+            .metabob_config
+            .as_ref()
+            .map(|path| expand_tilde(path))
+            .or_else(default_config_path);
+        let config = match &config_path {
+            Some(path) if path.exists() => MetabobConfig::load(path)?,
+            Some(path) if options.metabob_config.is_some() => {
+                return Err(MetabobError::config(format!(
+                    "Metabob config {} doesn't exist",
+                    path.display()
+                )))
+            }
+            _ => MetabobConfig::default(),
+        };
+        let profile_name = options
+            .profile
+            .clone()
+            .or_else(|| config.default_profile.clone());
+        let profile = config.profile(profile_name.as_deref())?.unwrap_or_default();
+
+        let (solana_rpc, solana_keypair, solana_commitment) = match solana_config {
+            Some(config) => (
+                Some(config.json_rpc_url),
+                Some(config.keypair_path),
+                Some(config.commitment),
+            ),
+            None => (None, None, None),
+        };
+
+        let rpc = match options.rpc.clone().or(profile.rpc).or(solana_rpc) {
+            Some(rpc) => rpc,
+            None => {
+                info!(
+                    "Could not find an RPC in a profile or a valid Solana-CLI config file. Defaulting to {} devnet node.",
+                    DEFAULT_RPC_URL
+                );
+                String::from(DEFAULT_RPC_URL)
+            }
+        };
+
+        let endpoints = if options.endpoints.is_empty() {
+            profile
+                .endpoints
+                .iter()
+                .map(|endpoint| EndpointConfig::from_str(endpoint))
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            options.endpoints.clone()
+        };
+
+        let commitment = options
+            .commitment
+            .clone()
+            .or(profile.commitment)
+            .or(solana_commitment)
+            .unwrap_or_else(|| String::from("confirmed"));
+        let commitment = CommitmentConfig::from_str(&commitment).map_err(|e| {
+            MetabobError::config_with_source(format!("Invalid commitment {}", commitment), e)
+        })?;
+
+        let output_dir = options
+            .output_dir
+            .clone()
+            .or(profile.output_dir)
+            .map(|dir| expand_tilde(&dir))
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        Ok(Settings {
+            profile: profile_name,
+            rpc,
+            heavy_rpc: options.heavy_rpc.clone().or(profile.heavy_rpc),
+            endpoints,
+            commitment,
+            keypair: options
+                .keypair
+                .clone()
+                .or(profile.keypair)
+                .or(solana_keypair)
+                .map(|path| expand_tilde(&path).to_string_lossy().into_owned()),
+            rps: options.rps.or(profile.rps),
+            in_flight: options
+                .in_flight
+                .or(profile.in_flight)
+                .unwrap_or(DEFAULT_IN_FLIGHT)
+                .max(1),
+            timeout: Duration::from_secs(
+                options
+                    .timeout
+                    .or(profile.timeout)
+                    .unwrap_or(DEFAULT_TIMEOUT_SECS),
+            ),
+            output_dir,
+            db: options
+                .db
+                .clone()
+                .or(profile.db)
+                .map(|path| expand_tilde(&path)),
+            priority_fee: options.priority_fee.or(profile.priority_fee),
+        })
+    }
+}
+
+impl Settings {
+    /// Creates the output dir, which saved files go in and get read back from
+    pub fn create_output_dir(&self) -> Result<()> {
+        fs::create_dir_all(&self.output_dir).map_err(MetabobError::io(&self.output_dir))
+    }
+}
+
+/// Reads a YAML or TOML file, going by the extension. `what` names it in errors
+pub fn load_config_file<T: DeserializeOwned>(path: &Path, what: &str) -> Result<T> {
+    let contents = fs::read_to_string(path).map_err(MetabobError::io(path))?;
+    let message = format!("Couldn't parse {} {}", what, path.display());
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => {
+            toml::from_str(&contents).map_err(|e| MetabobError::config_with_source(message, e))
+        }
+        _ => serde_yaml::from_str(&contents)
+            .map_err(|e| MetabobError::config_with_source(message, e)),
+    }
+}
+
+/// HOME on unix, USERPROFILE or HOMEDRIVE + HOMEPATH on windows. None if none of them are set
+pub fn home_dir() -> Option<PathBuf> {
+    let non_empty = |key| env::var_os(key).filter(|value| !value.is_empty());
+    non_empty("HOME")
+        .or_else(|| non_empty("USERPROFILE"))
+        .map(PathBuf::from)
+        .or_else(|| Some(Path::new(&non_empty("HOMEDRIVE")?).join(non_empty("HOMEPATH")?)))
+}
+
+pub fn expand_tilde(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            home.join(rest.trim_start_matches(['/', '\\']))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// ~/.config/metabob/config.yml, or config.toml if that's the one that exists
+pub fn default_config_path() -> Option<PathBuf> {
+    let dir = home_dir()?.join(".config").join("metabob");
+    ["config.yml", "config.yaml", "config.toml"]
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+}
@@ -3,7 +3,15 @@ pub const MAX_URI_LENGTH: usize = 200;
 pub const MAX_SYMBOL_LENGTH: usize = 10;
 pub const MAX_CREATOR_LEN: usize = 32 + 1 + 1;
 
+pub const GUMDROP_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("gdrpGjVffourzkdDRrQmySw4aTHr8a3xmQzzxSwFD1a");
+pub const CANDY_MACHINE_V1_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("cndyAnrLdpjq1Ssp1z8xxDsB8dxe7u4HL5Nxi2K5WXZ");
+pub const CANDY_MACHINE_V2_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("cndy3Z4yapfJBmL3ShUp5exZKqR3z33thTzeNMm2gRZ");
+
 use lazy_static::lazy_static;
+use solana_program::pubkey::Pubkey;
 /// Rest of this file all from metaboss
 use std::{collections::HashMap, sync::RwLock};
 pub const PUBLIC_RPC_URLS: &[&str] = &[
@@ -15,11 +23,57 @@ pub const PUBLIC_RPC_URLS: &[&str] = &[
 
 pub const DEFAULT_RPC_DELAY_MS: u32 = 200;
 
+pub const DEFAULT_RPC_URL: &str = "https://psytrbhymqlkfrhudd.dev.genesysgo.net:8899/";
+pub const DEFAULT_TIMEOUT_SECS: u64 = 60;
+pub const DEFAULT_IN_FLIGHT: usize = 64;
+pub const DEFAULT_MAX_LOGO_BYTES: u64 = 1024 * 1024;
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 64;
+
+pub const MINT_INFO_FILE: &str = "mint_info.ndjson";
+pub const ACCOUNT_INFO_FILE: &str = "account_info.ndjson";
+pub const FAILED_MINTS_FILE: &str = "failed_mints.json";
+pub const TOKEN_LIST_FILE: &str = "draft/tokenlist.ndjson";
+pub const URI_TOKEN_LIST_FILE: &str = "draft/uri_tokenlist.json";
+pub const NO_URI_TOKEN_LIST_FILE: &str = "draft/no_uri_tokenlist.json";
+pub const TOKEN_LIST_DIFF_FILE: &str = "draft/tokenlist_diff.json";
+pub const MERGED_TOKEN_LIST_FILE: &str = "draft/merged_tokenlist.json";
+pub const MERGE_CONFLICTS_FILE: &str = "draft/merge_conflicts.json";
+pub const REJECTED_TOKENS_FILE: &str = "draft/rejected_tokens.json";
+pub const LOGO_REPORT_FILE: &str = "draft/logo_report.json";
+
+/// Symbols worth impersonating and the mints they really belong to
+pub const DEFAULT_ALLOWLIST: &[(&str, &str)] = &[
+    ("SOL", "So11111111111111111111111111111111111111112"),
+    ("USDC", "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"),
+    ("USDT", "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB"),
+    ("mSOL", "mSoLzYCxHdYgdzU16g5QSh3i5K3z3KZK7ytfqcJm7So"),
+    ("BTC", "9n4nbM75f5Ui33ZbPYXn59EwSgE8CGsHtAeTH5YFeJ9E"),
+    ("ETH", "7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs"),
+    ("RAY", "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R"),
+    ("SRM", "SRMuApVNdxXokk5GT7XD5cUUgXMBCoAz2LHeuAoKWRt"),
+];
+
+/// Most accounts getMultipleAccounts will return in one call
+pub const METADATA_BATCH_SIZE: usize = 100;
+
+/// Mint scans are split into one getProgramAccounts call per value of this byte, the first
+/// byte of the mint authority, so only one shard of raw accounts is held at a time. Mints
+/// whose authority is disabled have it zeroed, so they all land in the first shard
+pub const MINT_SHARD_OFFSET: usize = 4;
+
+pub const RPC_MAX_RETRIES: u32 = 6;
+pub const RPC_POOL_MAX_RETRIES: u32 = 2;
+pub const RPC_BASE_BACKOFF_MS: u64 = 250;
+pub const RPC_MAX_BACKOFF_MS: u64 = 16_000;
+
 lazy_static! {
     pub static ref USE_RATE_LIMIT: RwLock<bool> = RwLock::new(false);
     pub static ref RPC_DELAY_NS: RwLock<u32> = RwLock::new(DEFAULT_RPC_DELAY_MS * 1_000_000);
+    pub static ref JSON_OUTPUT: RwLock<bool> = RwLock::new(false);
+    pub static ref QUIET: RwLock<bool> = RwLock::new(false);
+    /// Delay in milliseconds between requests for rpc hosts we know the limits of
     pub static ref RATE_LIMIT_DELAYS: HashMap<&'static str, u32> =
-        [("https://ssc-dao.genesysgo.net", 25),]
+        [("ssc-dao.genesysgo.net", 25),]
             .iter()
             .copied()
             .collect();
@@ -0,0 +1,814 @@
+use crate::errors::{MetabobError, Result};
+use crate::filter::Rejection;
+use crate::spl::{
+    AccountStruct, MetadataInfo, MetadataStruct, MintInfo, MintStruct, TokenExtensions,
+    TokenListEntry,
+};
+use rusqlite::{
+    params,
+    types::{Value, ValueRef},
+    Connection, Row,
+};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS mints (
+    mint TEXT PRIMARY KEY,
+    supply TEXT NOT NULL,
+    decimals INTEGER NOT NULL,
+    mint_authority TEXT,
+    freeze_authority TEXT,
+    is_initialized INTEGER NOT NULL DEFAULT 1,
+    has_authorities INTEGER NOT NULL DEFAULT 0,
+    slot INTEGER NOT NULL DEFAULT 0,
+    updated_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS metadata (
+    metadata TEXT PRIMARY KEY,
+    mint TEXT NOT NULL,
+    name TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    uri TEXT NOT NULL,
+    slot INTEGER NOT NULL DEFAULT 0,
+    updated_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS metadata_mint ON metadata (mint);
+CREATE TABLE IF NOT EXISTS failed_mints (
+    mint TEXT PRIMARY KEY,
+    updated_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS token_list (
+    address TEXT PRIMARY KEY,
+    chain_id INTEGER NOT NULL,
+    symbol TEXT NOT NULL,
+    name TEXT NOT NULL,
+    decimals INTEGER NOT NULL,
+    logo_uri TEXT NOT NULL,
+    supply TEXT,
+    mint_authority TEXT,
+    freeze_authority TEXT,
+    updated_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS rejected_tokens (
+    address TEXT PRIMARY KEY,
+    reasons TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS signatures (
+    metadata TEXT NOT NULL,
+    creator TEXT NOT NULL,
+    status TEXT NOT NULL,
+    signature TEXT,
+    error TEXT,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (metadata, creator)
+);
+";
+
+/// Columns added after a table was first made, for databases from older versions
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("mints", "slot", "INTEGER NOT NULL DEFAULT 0"),
+    ("mints", "mint_authority", "TEXT"),
+    ("mints", "freeze_authority", "TEXT"),
+    ("mints", "is_initialized", "INTEGER NOT NULL DEFAULT 1"),
+    ("mints", "has_authorities", "INTEGER NOT NULL DEFAULT 0"),
+    ("token_list", "supply", "TEXT"),
+    ("token_list", "mint_authority", "TEXT"),
+    ("token_list", "freeze_authority", "TEXT"),
+    ("metadata", "slot", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+/// What `get_mint` reads, in order
+const MINT_COLUMNS: &str =
+    "supply, decimals, mint_authority, freeze_authority, is_initialized, has_authorities";
+
+/// Unix time in SQLite, so every row written in one statement gets the same stamp
+const NOW: &str = "CAST(strftime('%s', 'now') AS INTEGER)";
+
+/// SQLite copy of everything the spl and metadata commands find. Writes are upserts keyed on
+/// the account address. `slot` is bumped every time a row is seen, but `updated_at` only moves
+/// when something else in it changes
+pub struct Store {
+    conn: Connection,
+    path: PathBuf,
+}
+
+/// Rows from an ad-hoc query, and how many rows it changed if it was an insert, update or delete
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryRows {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<JsonValue>>,
+    pub changes: usize,
+}
+
+impl Store {
+    /// Opens the database, creating it and its tables if they aren't there yet
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Store> {
+        let path = path.into();
+        let conn = Connection::open(&path).map_err(MetabobError::db(&path))?;
+        conn.execute_batch(SCHEMA)
+            .map_err(MetabobError::db(&path))?;
+        let store = Store { conn, path };
+        for (table, column, definition) in ADDED_COLUMNS {
+            store.add_column(table, column, definition)?;
+        }
+        store.retype_mint_supply()?;
+        Ok(store)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Runs `f` in one transaction, which is a lot faster than committing every upsert on its
+    /// own. Nothing from `f` is kept if it fails
+    pub fn in_transaction<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(MetabobError::db(&self.path))?;
+        let value = f()?;
+        tx.commit().map_err(MetabobError::db(&self.path))?;
+        Ok(value)
+    }
+
+    pub fn upsert_mint(&self, mint: &MintInfo) -> Result<()> {
+        self.execute(
+            &format!(
+                "INSERT INTO mints (mint, supply, decimals, mint_authority, freeze_authority,
+                    is_initialized, has_authorities, slot, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, {})
+                 ON CONFLICT (mint) DO UPDATE SET
+                    supply = excluded.supply, decimals = excluded.decimals,
+                    mint_authority = excluded.mint_authority,
+                    freeze_authority = excluded.freeze_authority,
+                    is_initialized = excluded.is_initialized,
+                    has_authorities = excluded.has_authorities, slot = max(slot, excluded.slot),
+                    updated_at = CASE
+                        WHEN supply IS NOT excluded.supply OR decimals IS NOT excluded.decimals
+                            OR mint_authority IS NOT excluded.mint_authority
+                            OR freeze_authority IS NOT excluded.freeze_authority
+                            OR is_initialized IS NOT excluded.is_initialized
+                            OR has_authorities IS NOT excluded.has_authorities
+                        THEN excluded.updated_at ELSE updated_at END",
+                NOW
+            ),
+            params![
+                mint.mint_pubkey.to_string(),
+                mint.mint.supply.to_string(),
+                mint.mint.decimals,
+                mint.mint.mint_authority.map(|key| key.to_string()),
+                mint.mint.freeze_authority.map(|key| key.to_string()),
+                mint.mint.is_initialized,
+                mint.mint.has_authorities,
+                u64_value(mint.slot)
+            ],
+        )
+    }
+
+    /// Saves the metadata along with its mint, and takes the mint off the failed list
+    pub fn upsert_account(&self, account: &AccountStruct) -> Result<()> {
+        self.upsert_mint(&account.mint)?;
+        let metadata = &account.metadata.metadata;
+        self.execute(
+            &format!(
+                "INSERT INTO metadata (metadata, mint, name, symbol, uri, slot, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, {})
+                 ON CONFLICT (metadata) DO UPDATE SET
+                    mint = excluded.mint, name = excluded.name, symbol = excluded.symbol,
+                    uri = excluded.uri, slot = max(slot, excluded.slot),
+                    updated_at = CASE
+                        WHEN mint IS NOT excluded.mint OR name IS NOT excluded.name
+                            OR symbol IS NOT excluded.symbol OR uri IS NOT excluded.uri
+                        THEN excluded.updated_at ELSE updated_at END",
+                NOW
+            ),
+            params![
+                account.metadata.metadata_pubkey.to_string(),
+                account.mint.mint_pubkey.to_string(),
+                trim_nul(&metadata.name),
+                trim_nul(&metadata.symbol),
+                trim_nul(&metadata.uri),
+                u64_value(account.metadata.slot)
+            ],
+        )?;
+        self.execute(
+            "DELETE FROM failed_mints WHERE mint = ?1",
+            params![account.mint.mint_pubkey.to_string()],
+        )
+    }
+
+    pub fn upsert_failed_mints(&self, mints: &[Pubkey]) -> Result<()> {
+        for mint in mints {
+            self.execute(
+                &format!(
+                    "INSERT INTO failed_mints (mint, updated_at) VALUES (?1, {})
+                     ON CONFLICT (mint) DO UPDATE SET updated_at = excluded.updated_at",
+                    NOW
+                ),
+                params![mint.to_string()],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the failed list, for runs that re-checked every mint on it
+    pub fn set_failed_mints(&self, mints: &[Pubkey]) -> Result<()> {
+        self.execute("DELETE FROM failed_mints", [])?;
+        self.upsert_failed_mints(mints)
+    }
+
+    pub fn failed_mints(&self) -> Result<Vec<Pubkey>> {
+        self.with_rows(
+            "SELECT mint FROM failed_mints ORDER BY mint",
+            |row| Ok(parse_key(&row.get::<_, String>(0)?)?),
+            |mints| mints.collect(),
+        )
+    }
+
+    /// Drops a mint's metadata and token list entry, e.g. once its metadata is gone
+    pub fn remove_token(&self, mint: &Pubkey) -> Result<()> {
+        let mint = mint.to_string();
+        self.execute("DELETE FROM metadata WHERE mint = ?1", params![mint])?;
+        self.execute("DELETE FROM token_list WHERE address = ?1", params![mint])?;
+        self.remove_rejection(&mint)
+    }
+
+    /// Drops a mint that's no longer a fungible mint, along with anything else about it
+    pub fn remove_mint(&self, mint: &Pubkey) -> Result<()> {
+        self.remove_token(mint)?;
+        let mint = mint.to_string();
+        self.execute("DELETE FROM mints WHERE mint = ?1", params![mint])?;
+        self.execute("DELETE FROM failed_mints WHERE mint = ?1", params![mint])
+    }
+
+    /// Saves the entry along with its supply and authority extensions. Any others are dropped
+    pub fn upsert_token_entry(&self, entry: &TokenListEntry) -> Result<()> {
+        let extensions = entry.extensions.clone().unwrap_or_default();
+        self.execute(
+            &format!(
+                "INSERT INTO token_list (address, chain_id, symbol, name, decimals, logo_uri,
+                    supply, mint_authority, freeze_authority, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, {})
+                 ON CONFLICT (address) DO UPDATE SET
+                    chain_id = excluded.chain_id, symbol = excluded.symbol, name = excluded.name,
+                    decimals = excluded.decimals, logo_uri = excluded.logo_uri,
+                    supply = excluded.supply, mint_authority = excluded.mint_authority,
+                    freeze_authority = excluded.freeze_authority,
+                    updated_at = excluded.updated_at
+                 WHERE chain_id IS NOT excluded.chain_id OR symbol IS NOT excluded.symbol
+                    OR name IS NOT excluded.name OR decimals IS NOT excluded.decimals
+                    OR logo_uri IS NOT excluded.logo_uri OR supply IS NOT excluded.supply
+                    OR mint_authority IS NOT excluded.mint_authority
+                    OR freeze_authority IS NOT excluded.freeze_authority",
+                NOW
+            ),
+            params![
+                entry.address,
+                entry.chain_id,
+                entry.symbol,
+                entry.name,
+                entry.decimals,
+                entry.logo_uri,
+                extensions.supply,
+                extensions.mint_authority,
+                extensions.freeze_authority
+            ],
+        )
+    }
+
+    /// Takes a token out of the token list and records why, as comma separated reason codes
+    pub fn upsert_rejection(&self, rejection: &Rejection) -> Result<()> {
+        let reasons: Vec<&str> = rejection
+            .reasons
+            .iter()
+            .map(|reason| reason.code.as_str())
+            .collect();
+        self.execute(
+            "DELETE FROM token_list WHERE address = ?1",
+            params![rejection.entry.address],
+        )?;
+        self.execute(
+            &format!(
+                "INSERT INTO rejected_tokens (address, reasons, updated_at) VALUES (?1, ?2, {})
+                 ON CONFLICT (address) DO UPDATE SET
+                    reasons = excluded.reasons, updated_at = excluded.updated_at
+                 WHERE reasons IS NOT excluded.reasons",
+                NOW
+            ),
+            params![rejection.entry.address, reasons.join(",")],
+        )
+    }
+
+    pub fn remove_rejection(&self, address: &str) -> Result<()> {
+        self.execute(
+            "DELETE FROM rejected_tokens WHERE address = ?1",
+            params![address],
+        )
+    }
+
+    /// Marks metadata as still needing `creator`'s signature
+    pub fn upsert_unverified(&self, creator: &Pubkey, metadata: &[Pubkey]) -> Result<()> {
+        for metadata in metadata {
+            self.upsert_signature(creator, metadata, "unverified", None, None)?;
+        }
+        Ok(())
+    }
+
+    pub fn upsert_signed(
+        &self,
+        creator: &Pubkey,
+        metadata: &Pubkey,
+        signature: &Signature,
+    ) -> Result<()> {
+        let signature = signature.to_string();
+        self.upsert_signature(creator, metadata, "signed", Some(&signature), None)
+    }
+
+    pub fn upsert_sign_failed(
+        &self,
+        creator: &Pubkey,
+        metadata: &Pubkey,
+        error: &str,
+    ) -> Result<()> {
+        self.upsert_signature(creator, metadata, "failed", None, Some(error))
+    }
+
+    /// Metadata accounts an earlier run already signed as `creator`
+    pub fn signed_metadata(&self, creator: &Pubkey) -> Result<HashSet<Pubkey>> {
+        let mut statement = self
+            .conn
+            .prepare_cached(
+                "SELECT metadata FROM signatures WHERE creator = ?1 AND status = 'signed'",
+            )
+            .map_err(MetabobError::db(&self.path))?;
+        let rows = statement
+            .query_map(params![creator.to_string()], |row| row.get::<_, String>(0))
+            .map_err(MetabobError::db(&self.path))?;
+        let mut signed = HashSet::new();
+        for metadata in rows {
+            signed.insert(parse_key(&metadata.map_err(MetabobError::db(&self.path))?)?);
+        }
+        Ok(signed)
+    }
+
+    pub fn count(&self, table: StoreTable) -> Result<u64> {
+        self.conn
+            .query_row(
+                &format!("SELECT COUNT(*) FROM {}", table.name()),
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|count| count as u64)
+            .map_err(MetabobError::db(&self.path))
+    }
+
+    /// Streams every stored mint through `f`
+    pub fn with_mints<T>(
+        &self,
+        f: impl FnOnce(&mut dyn Iterator<Item = Result<MintInfo>>) -> Result<T>,
+    ) -> Result<T> {
+        self.with_rows(
+            &format!(
+                "SELECT mint, slot, {} FROM mints ORDER BY mint",
+                MINT_COLUMNS
+            ),
+            |row| {
+                Ok(MintInfo {
+                    mint_pubkey: parse_key(&row.get::<_, String>(0)?)?,
+                    mint: get_mint(row, 2)?,
+                    slot: get_u64(row, 1)?,
+                })
+            },
+            f,
+        )
+    }
+
+    /// Streams every stored metadata account, joined with its mint, through `f`
+    pub fn with_accounts<T>(
+        &self,
+        f: impl FnOnce(&mut dyn Iterator<Item = Result<AccountStruct>>) -> Result<T>,
+    ) -> Result<T> {
+        self.with_rows(
+            &format!(
+                "SELECT metadata.mint, mints.slot, {}, metadata.metadata, name, symbol, uri,
+                    metadata.slot
+                 FROM metadata JOIN mints ON mints.mint = metadata.mint ORDER BY metadata.mint",
+                MINT_COLUMNS
+            ),
+            |row| {
+                let mint_pubkey = parse_key(&row.get::<_, String>(0)?)?;
+                Ok(AccountStruct::new(
+                    MintInfo {
+                        mint_pubkey,
+                        mint: get_mint(row, 2)?,
+                        slot: get_u64(row, 1)?,
+                    },
+                    MetadataInfo {
+                        metadata_pubkey: parse_key(&row.get::<_, String>(8)?)?,
+                        metadata: MetadataStruct {
+                            mint: mint_pubkey,
+                            name: row.get(9)?,
+                            symbol: row.get(10)?,
+                            uri: row.get(11)?,
+                        },
+                        slot: get_u64(row, 12)?,
+                    },
+                ))
+            },
+            f,
+        )
+    }
+
+    /// Streams every stored token list entry through `f`
+    pub fn with_token_entries<T>(
+        &self,
+        f: impl FnOnce(&mut dyn Iterator<Item = Result<TokenListEntry>>) -> Result<T>,
+    ) -> Result<T> {
+        self.with_rows(
+            "SELECT chain_id, address, symbol, name, decimals, logo_uri, supply, mint_authority,
+                freeze_authority
+             FROM token_list ORDER BY address",
+            |row| {
+                let supply: Option<String> = row.get(6)?;
+                Ok(TokenListEntry {
+                    chain_id: row.get(0)?,
+                    address: row.get(1)?,
+                    symbol: row.get(2)?,
+                    name: row.get(3)?,
+                    decimals: row.get(4)?,
+                    logo_uri: row.get(5)?,
+                    extensions: match supply {
+                        Some(supply) => Some(TokenExtensions {
+                            supply: Some(supply),
+                            mint_authority: row.get(7)?,
+                            freeze_authority: row.get(8)?,
+                            other: BTreeMap::new(),
+                        }),
+                        None => None,
+                    },
+                    other: BTreeMap::new(),
+                })
+            },
+            f,
+        )
+    }
+
+    /// Runs any SQL statement. Blobs come back as base58
+    pub fn query(&self, sql: &str) -> Result<QueryRows> {
+        let mut statement = self
+            .conn
+            .prepare(sql)
+            .map_err(MetabobError::db(&self.path))?;
+        let columns: Vec<String> = statement
+            .column_names()
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let mut rows = Vec::new();
+        let mut results = statement.query([]).map_err(MetabobError::db(&self.path))?;
+        while let Some(row) = results.next().map_err(MetabobError::db(&self.path))? {
+            let mut values = Vec::with_capacity(columns.len());
+            for i in 0..columns.len() {
+                values.push(json_value(
+                    row.get_ref(i).map_err(MetabobError::db(&self.path))?,
+                ));
+            }
+            rows.push(values);
+        }
+        drop(results);
+        let changes = self
+            .conn
+            .query_row("SELECT changes()", [], |row| row.get::<_, i64>(0))
+            .map_err(MetabobError::db(&self.path))?;
+        Ok(QueryRows {
+            columns,
+            rows,
+            changes: changes as usize,
+        })
+    }
+
+    fn add_column(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let exists = self.with_rows(
+            &format!("SELECT name FROM pragma_table_info('{}')", table),
+            |row| Ok(row.get::<_, String>(0)?),
+            |names| {
+                for name in names {
+                    if name? == column {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            },
+        )?;
+        if !exists {
+            self.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+                [],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Older databases declared `mints.supply` an INTEGER, which turns supplies past i64::MAX
+    /// into lossy REALs. Copies the table into one where it's TEXT, dropping any mint whose
+    /// supply was already mangled so the next scan fetches it again
+    fn retype_mint_supply(&self) -> Result<()> {
+        let declared = self.with_rows(
+            "SELECT type FROM pragma_table_info('mints') WHERE name = 'supply'",
+            |row| Ok(row.get::<_, String>(0)?),
+            |types| types.next().transpose(),
+        )?;
+        if !matches!(declared, Some(declared) if declared.eq_ignore_ascii_case("INTEGER")) {
+            return Ok(());
+        }
+        self.in_transaction(|| {
+            self.execute("ALTER TABLE mints RENAME TO mints_integer_supply", [])?;
+            self.conn
+                .execute_batch(SCHEMA)
+                .map_err(MetabobError::db(&self.path))?;
+            self.execute(
+                "INSERT INTO mints (mint, supply, decimals, mint_authority, freeze_authority,
+                    is_initialized, has_authorities, slot, updated_at)
+                 SELECT mint, CAST(supply AS TEXT), decimals, mint_authority, freeze_authority,
+                    is_initialized, has_authorities, slot, updated_at
+                 FROM mints_integer_supply WHERE typeof(supply) IN ('integer', 'text')",
+                [],
+            )?;
+            self.execute("DROP TABLE mints_integer_supply", [])
+        })
+    }
+
+    fn upsert_signature(
+        &self,
+        creator: &Pubkey,
+        metadata: &Pubkey,
+        status: &str,
+        signature: Option<&str>,
+        error: Option<&str>,
+    ) -> Result<()> {
+        self.execute(
+            &format!(
+                "INSERT INTO signatures (metadata, creator, status, signature, error, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, {})
+                 ON CONFLICT (metadata, creator) DO UPDATE SET
+                    status = excluded.status, signature = excluded.signature,
+                    error = excluded.error, updated_at = excluded.updated_at
+                 WHERE status IS NOT excluded.status OR signature IS NOT excluded.signature
+                    OR error IS NOT excluded.error",
+                NOW
+            ),
+            params![
+                metadata.to_string(),
+                creator.to_string(),
+                status,
+                signature,
+                error
+            ],
+        )
+    }
+
+    fn execute<P: rusqlite::Params>(&self, sql: &str, params: P) -> Result<()> {
+        self.conn
+            .prepare_cached(sql)
+            .and_then(|mut statement| statement.execute(params))
+            .map(|_| ())
+            .map_err(MetabobError::db(&self.path))
+    }
+
+    fn with_rows<R, T>(
+        &self,
+        sql: &str,
+        read: impl Fn(&Row) -> Result<R, RowError>,
+        f: impl FnOnce(&mut dyn Iterator<Item = Result<R>>) -> Result<T>,
+    ) -> Result<T> {
+        let mut statement = self
+            .conn
+            .prepare(sql)
+            .map_err(MetabobError::db(&self.path))?;
+        let rows = statement
+            .query_map([], |row| Ok(read(row)))
+            .map_err(MetabobError::db(&self.path))?;
+        let mut rows = rows.map(|row| match row {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(RowError::Sql(e))) | Err(e) => Err(MetabobError::db(&self.path)(e)),
+            Ok(Err(RowError::Other(e))) => Err(e),
+        });
+        f(&mut rows)
+    }
+}
+
+/// Runs `f` in a transaction on `store` if there is one, or just runs it
+pub fn transaction<T>(store: Option<&Store>, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    match store {
+        Some(store) => store.in_transaction(f),
+        None => f(),
+    }
+}
+
+/// The tables the spl stages fill in, for counting rows up front
+#[derive(Debug, Clone, Copy)]
+pub enum StoreTable {
+    Mints,
+    Metadata,
+    TokenList,
+}
+
+impl fmt::Display for StoreTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl StoreTable {
+    fn name(self) -> &'static str {
+        match self {
+            StoreTable::Mints => "mints",
+            StoreTable::Metadata => "metadata",
+            StoreTable::TokenList => "token_list",
+        }
+    }
+}
+
+/// Errors while turning a row back into a record, which can be SQLite's or ours
+enum RowError {
+    Sql(rusqlite::Error),
+    Other(MetabobError),
+}
+
+impl From<rusqlite::Error> for RowError {
+    fn from(e: rusqlite::Error) -> Self {
+        RowError::Sql(e)
+    }
+}
+
+impl From<MetabobError> for RowError {
+    fn from(e: MetabobError) -> Self {
+        RowError::Other(e)
+    }
+}
+
+fn parse_key(value: &str) -> Result<Pubkey> {
+    Pubkey::from_str(value).map_err(|source| MetabobError::InvalidPubkey {
+        value: value.to_string(),
+        source,
+    })
+}
+
+/// SQLite integers are signed, so values past i64::MAX are kept as text. Columns that can
+/// hold those, like a mint's supply, are TEXT and get the decimal string instead
+fn u64_value(value: u64) -> Value {
+    match i64::try_from(value) {
+        Ok(value) => Value::Integer(value),
+        Err(_) => Value::Text(value.to_string()),
+    }
+}
+
+fn get_u64(row: &Row, i: usize) -> Result<u64, RowError> {
+    let value = row.get_ref(i)?;
+    let parsed = match value {
+        ValueRef::Integer(value) => u64::try_from(value).ok(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).parse().ok(),
+        _ => None,
+    };
+    parsed.ok_or_else(|| {
+        rusqlite::Error::InvalidColumnType(i, String::from("u64"), value.data_type()).into()
+    })
+}
+
+/// A mint from the `MINT_COLUMNS` starting at column `first`
+fn get_mint(row: &Row, first: usize) -> Result<MintStruct, RowError> {
+    Ok(MintStruct {
+        supply: get_u64(row, first)?,
+        decimals: row.get(first + 1)?,
+        mint_authority: get_optional_key(row, first + 2)?,
+        freeze_authority: get_optional_key(row, first + 3)?,
+        is_initialized: row.get(first + 4)?,
+        has_authorities: row.get(first + 5)?,
+    })
+}
+
+fn get_optional_key(row: &Row, i: usize) -> Result<Option<Pubkey>, RowError> {
+    match row.get::<_, Option<String>>(i)? {
+        Some(key) => Ok(Some(parse_key(&key)?)),
+        None => Ok(None),
+    }
+}
+
+fn json_value(value: ValueRef) -> JsonValue {
+    match value {
+        ValueRef::Null => JsonValue::Null,
+        ValueRef::Integer(value) => JsonValue::from(value),
+        ValueRef::Real(value) => JsonValue::from(value),
+        ValueRef::Text(text) => JsonValue::from(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(blob) => JsonValue::from(bs58::encode(blob).into_string()),
+    }
+}
+
+/// Names, symbols and uris come off chain padded with NULs
+fn trim_nul(value: &str) -> &str {
+    value.trim_matches(char::from(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spl::MintStruct;
+
+    fn mint(supply: u64) -> MintInfo {
+        MintInfo {
+            mint_pubkey: Pubkey::new_unique(),
+            mint: MintStruct {
+                supply,
+                decimals: 6,
+                mint_authority: Some(Pubkey::new_unique()),
+                freeze_authority: None,
+                is_initialized: true,
+                has_authorities: true,
+            },
+            slot: 42,
+        }
+    }
+
+    fn stored_mints(store: &Store) -> Vec<MintInfo> {
+        store.with_mints(|mints| mints.collect()).unwrap()
+    }
+
+    fn temp_db(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("metabob-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn supply_past_i64_max_round_trips() {
+        let store = Store::open(":memory:").unwrap();
+        let mut expected = vec![mint(u64::MAX), mint(0), mint(i64::MAX as u64 + 1)];
+        for mint in &expected {
+            store.upsert_mint(mint).unwrap();
+        }
+        // Upserting the same supply again must not trip over the stored text
+        store.upsert_mint(&expected[0]).unwrap();
+
+        expected.sort_by_key(|mint| mint.mint_pubkey.to_string());
+        let stored = stored_mints(&store);
+        assert_eq!(stored.len(), expected.len());
+        for (stored, expected) in stored.iter().zip(&expected) {
+            assert_eq!(stored.mint_pubkey, expected.mint_pubkey);
+            assert_eq!(stored.mint.supply, expected.mint.supply);
+            assert_eq!(stored.mint.mint_authority, expected.mint.mint_authority);
+        }
+    }
+
+    #[test]
+    fn integer_supply_column_is_retyped() {
+        let path = temp_db("integer-supply");
+        let kept = Pubkey::new_unique();
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE mints (
+                    mint TEXT PRIMARY KEY,
+                    supply INTEGER NOT NULL,
+                    decimals INTEGER NOT NULL,
+                    updated_at INTEGER NOT NULL
+                );",
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO mints (mint, supply, decimals, updated_at) VALUES (?1, 5, 0, 0)",
+                params![kept.to_string()],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO mints (mint, supply, decimals, updated_at) VALUES (?1, ?2, 0, 0)",
+                params![Pubkey::new_unique().to_string(), u64::MAX.to_string()],
+            )
+            .unwrap();
+        }
+
+        let store = Store::open(&path).unwrap();
+        let stored = stored_mints(&store);
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].mint_pubkey, kept);
+        assert_eq!(stored[0].mint.supply, 5);
+
+        let big = mint(u64::MAX);
+        store.upsert_mint(&big).unwrap();
+        drop(store);
+        let store = Store::open(&path).unwrap();
+        assert!(stored_mints(&store)
+            .iter()
+            .any(|mint| mint.mint_pubkey == big.mint_pubkey && mint.mint.supply == u64::MAX));
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,26 +1,17 @@
 use crate::errors::DecodeError;
-use metaplex_token_metadata::state::Metadata;
-use metaplex_token_metadata::ID as TOKEN_METADATA_PROGRAM_ID;
-use solana_program::{borsh::try_from_slice_unchecked, program_pack::Pack};
+use log::debug;
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use solana_program::program_pack::Pack;
 use solana_sdk::{account::Account, pubkey::Pubkey};
 use spl_token::state::Mint;
-use std::io::Error;
-
-pub fn get_metadata_pda(mint_pubkey: &Pubkey) -> Pubkey {
-    let seeds = &[
-        "metadata".as_bytes(),
-        TOKEN_METADATA_PROGRAM_ID.as_ref(),
-        mint_pubkey.as_ref(),
-    ];
-    let (pubkey, _) = Pubkey::find_program_address(seeds, &TOKEN_METADATA_PROGRAM_ID);
-    pubkey
-}
+use std::io::{Error, ErrorKind};
 
 pub fn decode_mint_account(mint_account: &Account) -> Result<Mint, DecodeError> {
     let account_data = mint_account.data.as_slice();
     let mint_data: Mint = match spl_token::state::Mint::unpack(account_data) {
         Ok(m) => m,
-        Err(err) => return Err(DecodeError::DecodeMintFailed(err.to_string())),
+        Err(err) => return Err(DecodeError::DecodeMintFailed(err)),
     };
     Ok(mint_data)
 }
@@ -29,12 +20,716 @@ pub fn decode_mint_account(mint_account: &Account) -> Result<Mint, DecodeError>
 //     debug_account_data(data, f);
 // }
 
+/// The first byte of every token metadata program account
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Key {
+    Uninitialized,
+    EditionV1,
+    MasterEditionV1,
+    ReservationListV1,
+    MetadataV1,
+    ReservationListV2,
+    MasterEditionV2,
+    EditionMarker,
+    UseAuthorityRecord,
+    CollectionAuthorityRecord,
+    TokenOwnedEscrow,
+    TokenRecord,
+    MetadataDelegate,
+    EditionMarkerV2,
+}
+
+impl Key {
+    pub fn from_u8(byte: u8) -> Option<Key> {
+        use Key::*;
+        [
+            Uninitialized,
+            EditionV1,
+            MasterEditionV1,
+            ReservationListV1,
+            MetadataV1,
+            ReservationListV2,
+            MasterEditionV2,
+            EditionMarker,
+            UseAuthorityRecord,
+            CollectionAuthorityRecord,
+            TokenOwnedEscrow,
+            TokenRecord,
+            MetadataDelegate,
+            EditionMarkerV2,
+        ]
+        .get(byte as usize)
+        .copied()
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Creator {
+    #[serde(serialize_with = "base58")]
+    pub address: Pubkey,
+    pub verified: bool,
+    /// Percent of the creators' royalties
+    pub share: u8,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenStandard {
+    NonFungible,
+    FungibleAsset,
+    Fungible,
+    NonFungibleEdition,
+    ProgrammableNonFungible,
+    ProgrammableNonFungibleEdition,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Collection {
+    pub verified: bool,
+    #[serde(serialize_with = "base58")]
+    pub key: Pubkey,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UseMethod {
+    Burn,
+    Multiple,
+    Single,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Uses {
+    pub use_method: UseMethod,
+    pub remaining: u64,
+    pub total: u64,
+}
+
+/// Only set on the NFT a sized collection belongs to
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectionDetails {
+    V1 {
+        size: u64,
+    },
+    /// Size is tracked elsewhere from V2 on
+    V2,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgrammableConfig {
+    V1 {
+        #[serde(serialize_with = "optional_base58")]
+        rule_set: Option<Pubkey>,
+    },
+}
+
+/// A token metadata account, from any version of the program. Everything after `is_mutable`
+/// was added over time, so it's None on accounts from before it existed
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    #[serde(serialize_with = "base58")]
+    pub update_authority: Pubkey,
+    #[serde(serialize_with = "base58")]
+    pub mint: Pubkey,
+    /// Still padded with NULs, as it is on chain. They're left out when serialized
+    #[serde(serialize_with = "nul_trimmed")]
+    pub name: String,
+    #[serde(serialize_with = "nul_trimmed")]
+    pub symbol: String,
+    #[serde(serialize_with = "nul_trimmed")]
+    pub uri: String,
+    pub seller_fee_basis_points: u16,
+    pub creators: Option<Vec<Creator>>,
+    pub primary_sale_happened: bool,
+    pub is_mutable: bool,
+    pub edition_nonce: Option<u8>,
+    pub token_standard: Option<TokenStandard>,
+    pub collection: Option<Collection>,
+    pub uses: Option<Uses>,
+    pub collection_details: Option<CollectionDetails>,
+    pub programmable_config: Option<ProgrammableConfig>,
+}
+
 pub fn decode_metadata_account(account: &Account) -> Result<Metadata, DecodeError> {
-    let account_data = account.data.as_slice();
-    let metadata: Result<Metadata, Error> = try_from_slice_unchecked(account_data);
-    let token_metadata = match metadata {
-        Ok(m) => m,
-        Err(err) => return Err(DecodeError::DecodeMetadataDataFailed(err.to_string())),
+    decode_metadata(&account.data)
+}
+
+/// Decodes metadata account data. Everything up to `is_mutable` has to be there. The fields
+/// added since are read while they make sense: old accounts end early or carry junk in the
+/// padding, so the first one that doesn't decode and everything after it are left as None.
+/// Names, symbols and uris that aren't valid UTF-8 get replacement characters
+pub fn decode_metadata(data: &[u8]) -> Result<Metadata, DecodeError> {
+    let mut reader = Reader::new(data);
+    let mut metadata = read_metadata(&mut reader).map_err(DecodeError::DecodeMetadataDataFailed)?;
+    if let Err(e) = read_extensions(&mut reader, &mut metadata) {
+        debug!(
+            "Metadata for {} stops making sense at byte {}: {}",
+            metadata.mint, reader.pos, e
+        );
+    }
+    Ok(metadata)
+}
+
+fn read_metadata(reader: &mut Reader) -> Result<Metadata, Error> {
+    match Key::from_u8(reader.u8()?) {
+        Some(Key::MetadataV1) => {}
+        Some(key) => return Err(invalid(format!("{:?} account, not metadata", key))),
+        None => return Err(invalid("unknown account key")),
+    }
+    Ok(Metadata {
+        update_authority: reader.pubkey()?,
+        mint: reader.pubkey()?,
+        name: reader.string()?,
+        symbol: reader.string()?,
+        uri: reader.string()?,
+        seller_fee_basis_points: reader.u16()?,
+        creators: reader.option(|reader| {
+            let len = reader.u32()?;
+            (0..len)
+                .map(|_| {
+                    Ok(Creator {
+                        address: reader.pubkey()?,
+                        verified: reader.bool()?,
+                        share: reader.u8()?,
+                    })
+                })
+                .collect()
+        })?,
+        primary_sale_happened: reader.bool()?,
+        is_mutable: reader.bool()?,
+        edition_nonce: None,
+        token_standard: None,
+        collection: None,
+        uses: None,
+        collection_details: None,
+        programmable_config: None,
+    })
+}
+
+fn read_extensions(reader: &mut Reader, metadata: &mut Metadata) -> Result<(), Error> {
+    if reader.is_empty() {
+        return Ok(());
+    }
+    metadata.edition_nonce = reader.option(Reader::u8)?;
+    if reader.is_empty() {
+        return Ok(());
+    }
+    metadata.token_standard = reader.option(|reader| {
+        use TokenStandard::*;
+        match reader.u8()? {
+            0 => Ok(NonFungible),
+            1 => Ok(FungibleAsset),
+            2 => Ok(Fungible),
+            3 => Ok(NonFungibleEdition),
+            4 => Ok(ProgrammableNonFungible),
+            5 => Ok(ProgrammableNonFungibleEdition),
+            other => Err(invalid(format!("unknown token standard {}", other))),
+        }
+    })?;
+    if reader.is_empty() {
+        return Ok(());
+    }
+    metadata.collection = reader.option(|reader| {
+        Ok(Collection {
+            verified: reader.bool()?,
+            key: reader.pubkey()?,
+        })
+    })?;
+    if reader.is_empty() {
+        return Ok(());
+    }
+    metadata.uses = reader.option(|reader| {
+        let use_method = match reader.u8()? {
+            0 => UseMethod::Burn,
+            1 => UseMethod::Multiple,
+            2 => UseMethod::Single,
+            other => return Err(invalid(format!("unknown use method {}", other))),
+        };
+        Ok(Uses {
+            use_method,
+            remaining: reader.u64()?,
+            total: reader.u64()?,
+        })
+    })?;
+    if reader.is_empty() {
+        return Ok(());
+    }
+    metadata.collection_details = reader.option(|reader| match reader.u8()? {
+        0 => Ok(CollectionDetails::V1 {
+            size: reader.u64()?,
+        }),
+        1 => {
+            reader.take(8)?;
+            Ok(CollectionDetails::V2)
+        }
+        other => Err(invalid(format!("unknown collection details {}", other))),
+    })?;
+    if reader.is_empty() {
+        return Ok(());
+    }
+    metadata.programmable_config = reader.option(|reader| match reader.u8()? {
+        0 => Ok(ProgrammableConfig::V1 {
+            rule_set: reader.option(Reader::pubkey)?,
+        }),
+        other => Err(invalid(format!("unknown programmable config {}", other))),
+    })?;
+    Ok(())
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MasterEdition {
+    pub supply: u64,
+    /// None if it can print forever
+    pub max_supply: Option<u64>,
+    /// Only V1 master editions had these
+    #[serde(
+        serialize_with = "optional_base58",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub printing_mint: Option<Pubkey>,
+    #[serde(
+        serialize_with = "optional_base58",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub one_time_printing_authorization_mint: Option<Pubkey>,
+}
+
+/// A print of a master edition
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Edition {
+    /// The master edition account it was printed from
+    #[serde(serialize_with = "base58")]
+    pub parent: Pubkey,
+    pub edition: u64,
+}
+
+/// Which editions of a master edition have been printed, 248 to a marker
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EditionMarker {
+    /// Offsets into the marker's range of the editions that have been printed. Marker `n`
+    /// starts at edition `n * 248`
+    pub printed: Vec<u32>,
+}
+
+pub fn decode_master_edition(data: &[u8]) -> Result<MasterEdition, DecodeError> {
+    let mut reader = Reader::new(data);
+    let read = |reader: &mut Reader| {
+        let v1 = match Key::from_u8(reader.u8()?) {
+            Some(Key::MasterEditionV1) => true,
+            Some(Key::MasterEditionV2) => false,
+            _ => return Err(invalid("not a master edition")),
+        };
+        Ok(MasterEdition {
+            supply: reader.u64()?,
+            max_supply: reader.option(Reader::u64)?,
+            printing_mint: if v1 { Some(reader.pubkey()?) } else { None },
+            one_time_printing_authorization_mint: if v1 { Some(reader.pubkey()?) } else { None },
+        })
+    };
+    read(&mut reader).map_err(failed("master edition"))
+}
+
+pub fn decode_edition(data: &[u8]) -> Result<Edition, DecodeError> {
+    let mut reader = Reader::new(data);
+    let read = |reader: &mut Reader| {
+        if Key::from_u8(reader.u8()?) != Some(Key::EditionV1) {
+            return Err(invalid("not an edition"));
+        }
+        Ok(Edition {
+            parent: reader.pubkey()?,
+            edition: reader.u64()?,
+        })
+    };
+    read(&mut reader).map_err(failed("edition"))
+}
+
+pub fn decode_edition_marker(data: &[u8]) -> Result<EditionMarker, DecodeError> {
+    let mut reader = Reader::new(data);
+    let read = |reader: &mut Reader| {
+        let ledger = match Key::from_u8(reader.u8()?) {
+            Some(Key::EditionMarker) => reader.take(31)?,
+            Some(Key::EditionMarkerV2) => {
+                let len = reader.u32()? as usize;
+                reader.take(len)?
+            }
+            _ => return Err(invalid("not an edition marker")),
+        };
+        // Most significant bit first, so edition 0 of the marker is the top bit of byte 0
+        let printed = ledger
+            .iter()
+            .enumerate()
+            .flat_map(|(i, byte)| {
+                (0..8)
+                    .filter(move |bit| byte & (0x80 >> bit) != 0)
+                    .map(move |bit| (i * 8 + bit) as u32)
+            })
+            .collect();
+        Ok(EditionMarker { printed })
+    };
+    read(&mut reader).map_err(failed("edition marker"))
+}
+
+/// The first 8 bytes of an Anchor account, which say what type it is
+pub fn anchor_discriminator(account_name: &str) -> [u8; 8] {
+    let hash = Sha256::digest(format!("account:{}", account_name).as_bytes());
+    let mut discriminator = [0; 8];
+    discriminator.copy_from_slice(&hash[..8]);
+    discriminator
+}
+
+/// Whether `data` is an Anchor account of type `account_name`
+pub fn is_anchor_account(data: &[u8], account_name: &str) -> bool {
+    data.len() >= 8 && data[..8] == anchor_discriminator(account_name)
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EndSettings {
+    /// `date` or `amount`
+    pub end_setting_type: String,
+    pub number: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HiddenSettings {
+    pub name: String,
+    pub uri: String,
+    /// Hex
+    pub hash: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct WhitelistMintSettings {
+    /// `burn_every_time` or `never_burn`
+    pub mode: String,
+    #[serde(serialize_with = "base58")]
+    pub mint: Pubkey,
+    pub presale: bool,
+    pub discount_price: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Gatekeeper {
+    #[serde(serialize_with = "base58")]
+    pub gatekeeper_network: Pubkey,
+    pub expire_on_use: bool,
+}
+
+/// A candy machine from either version of the program. The V2 settings are None on V1
+/// machines, and `config` is only on V1
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CandyMachine {
+    pub version: u8,
+    #[serde(serialize_with = "base58")]
+    pub authority: Pubkey,
+    /// Where the SOL or tokens paid go
+    #[serde(serialize_with = "base58")]
+    pub wallet: Pubkey,
+    /// The SPL token it takes payment in, if not SOL
+    #[serde(serialize_with = "optional_base58")]
+    pub token_mint: Option<Pubkey>,
+    #[serde(
+        serialize_with = "optional_base58",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub config: Option<Pubkey>,
+    pub uuid: String,
+    pub price: u64,
+    pub items_available: u64,
+    pub items_redeemed: u64,
+    /// Unix time
+    pub go_live_date: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seller_fee_basis_points: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_supply: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_mutable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retain_authority: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub creators: Vec<Creator>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_settings: Option<EndSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hidden_settings: Option<HiddenSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whitelist_mint_settings: Option<WhitelistMintSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gatekeeper: Option<Gatekeeper>,
+}
+
+impl CandyMachine {
+    fn v1(reader: &mut Reader) -> Result<CandyMachine, Error> {
+        let authority = reader.pubkey()?;
+        let wallet = reader.pubkey()?;
+        let token_mint = reader.option(Reader::pubkey)?;
+        let config = reader.pubkey()?;
+        let uuid = reader.string()?;
+        let price = reader.u64()?;
+        let items_available = reader.u64()?;
+        let go_live_date = reader.option(Reader::i64)?;
+        Ok(CandyMachine {
+            version: 1,
+            authority,
+            wallet,
+            token_mint,
+            config: Some(config),
+            uuid,
+            price,
+            items_available,
+            items_redeemed: reader.u64()?,
+            go_live_date,
+            symbol: None,
+            seller_fee_basis_points: None,
+            max_supply: None,
+            is_mutable: None,
+            retain_authority: None,
+            creators: Vec::new(),
+            end_settings: None,
+            hidden_settings: None,
+            whitelist_mint_settings: None,
+            gatekeeper: None,
+        })
+    }
+
+    fn v2(reader: &mut Reader) -> Result<CandyMachine, Error> {
+        let authority = reader.pubkey()?;
+        let wallet = reader.pubkey()?;
+        let token_mint = reader.option(Reader::pubkey)?;
+        let items_redeemed = reader.u64()?;
+        let uuid = reader.string()?;
+        let price = reader.u64()?;
+        let symbol = reader.string()?;
+        let seller_fee_basis_points = reader.u16()?;
+        let max_supply = reader.u64()?;
+        let is_mutable = reader.bool()?;
+        let retain_authority = reader.bool()?;
+        let go_live_date = reader.option(Reader::i64)?;
+        let end_settings = reader.option(|reader| {
+            Ok(EndSettings {
+                end_setting_type: match reader.u8()? {
+                    0 => String::from("date"),
+                    1 => String::from("amount"),
+                    other => return Err(invalid(format!("unknown end setting {}", other))),
+                },
+                number: reader.u64()?,
+            })
+        })?;
+        let creators = (0..reader.u32()?)
+            .map(|_| {
+                Ok(Creator {
+                    address: reader.pubkey()?,
+                    verified: reader.bool()?,
+                    share: reader.u8()?,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        let hidden_settings = reader.option(|reader| {
+            Ok(HiddenSettings {
+                name: reader.string()?,
+                uri: reader.string()?,
+                hash: hex(reader.take(32)?),
+            })
+        })?;
+        let whitelist_mint_settings = reader.option(|reader| {
+            Ok(WhitelistMintSettings {
+                mode: match reader.u8()? {
+                    0 => String::from("burn_every_time"),
+                    1 => String::from("never_burn"),
+                    other => return Err(invalid(format!("unknown whitelist mode {}", other))),
+                },
+                mint: reader.pubkey()?,
+                presale: reader.bool()?,
+                discount_price: reader.option(Reader::u64)?,
+            })
+        })?;
+        let items_available = reader.u64()?;
+        let gatekeeper = reader.option(|reader| {
+            Ok(Gatekeeper {
+                gatekeeper_network: reader.pubkey()?,
+                expire_on_use: reader.bool()?,
+            })
+        })?;
+        Ok(CandyMachine {
+            version: 2,
+            authority,
+            wallet,
+            token_mint,
+            config: None,
+            uuid,
+            price,
+            items_available,
+            items_redeemed,
+            go_live_date,
+            symbol: Some(symbol),
+            seller_fee_basis_points: Some(seller_fee_basis_points),
+            max_supply: Some(max_supply),
+            is_mutable: Some(is_mutable),
+            retain_authority: Some(retain_authority),
+            creators,
+            end_settings,
+            hidden_settings,
+            whitelist_mint_settings,
+            gatekeeper,
+        })
+    }
+}
+
+pub fn decode_candy_machine(data: &[u8], version: u8) -> Result<CandyMachine, DecodeError> {
+    if !is_anchor_account(data, "CandyMachine") {
+        return Err(failed("candy machine")(invalid("not a candy machine")));
+    }
+    let mut reader = Reader::new(&data[8..]);
+    match version {
+        1 => CandyMachine::v1(&mut reader),
+        _ => CandyMachine::v2(&mut reader),
+    }
+    .map_err(failed("candy machine"))
+}
+
+/// A gumdrop merkle distributor
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Distributor {
+    /// The key the distributor address was derived from
+    #[serde(serialize_with = "base58")]
+    pub base: Pubkey,
+    pub bump: u8,
+    /// Hex merkle root of the claims
+    pub root: String,
+    /// Signs off on claims alongside the claimant, if it isn't the default pubkey
+    #[serde(serialize_with = "base58")]
+    pub temporal: Pubkey,
+}
+
+pub fn decode_distributor(data: &[u8]) -> Result<Distributor, DecodeError> {
+    if !is_anchor_account(data, "MerkleDistributor") {
+        return Err(failed("gumdrop distributor")(invalid(
+            "not a merkle distributor",
+        )));
+    }
+    let mut reader = Reader::new(&data[8..]);
+    let read = |reader: &mut Reader| {
+        Ok(Distributor {
+            base: reader.pubkey()?,
+            bump: reader.u8()?,
+            root: hex(reader.take(32)?),
+            temporal: reader.pubkey()?,
+        })
     };
-    Ok(token_metadata)
+    read(&mut reader).map_err(failed("gumdrop distributor"))
+}
+
+fn failed(kind: &'static str) -> impl Fn(Error) -> DecodeError {
+    move |source| DecodeError::DecodeAccountFailed { kind, source }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Reads borsh a field at a time, so a short account fails where it runs out
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!("account ends before byte {}", self.pos.saturating_add(len)),
+                )
+            })?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(invalid(format!("{} isn't a bool", other))),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, Error> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn pubkey(&mut self) -> Result<Pubkey, Error> {
+        Ok(Pubkey::new_from_array(self.take(32)?.try_into().unwrap()))
+    }
+
+    pub fn string(&mut self) -> Result<String, Error> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    pub fn option<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<Option<T>, Error> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => read(self).map(Some),
+            other => Err(invalid(format!("{} isn't an option tag", other))),
+        }
+    }
+}
+
+fn invalid<S: Into<String>>(message: S) -> Error {
+    Error::new(ErrorKind::InvalidData, message.into())
+}
+
+fn nul_trimmed<S: Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(value.trim_matches(char::from(0)))
+}
+
+pub fn base58<S: Serializer>(key: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(key)
+}
+
+pub fn optional_base58<S: Serializer>(
+    key: &Option<Pubkey>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match key {
+        Some(key) => serializer.collect_str(key),
+        None => serializer.serialize_none(),
+    }
 }
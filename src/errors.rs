@@ -1,10 +1,137 @@
+use solana_client::client_error::ClientError;
+use solana_program::{program_error::ProgramError, pubkey::ParsePubkeyError};
+use std::{error::Error as StdError, io, path::PathBuf};
 use thiserror::Error;
 
+pub type Result<T, E = MetabobError> = std::result::Result<T, E>;
+
 #[derive(Error, Debug)]
 pub enum DecodeError {
     #[error("failed to decode token mint data")]
-    DecodeMintFailed(String),
+    DecodeMintFailed(#[source] ProgramError),
 
     #[error("failed to decode token metadata data")]
-    DecodeMetadataDataFailed(String),
+    DecodeMetadataDataFailed(#[source] io::Error),
+
+    #[error("failed to decode {kind} account")]
+    DecodeAccountFailed {
+        kind: &'static str,
+        #[source]
+        source: io::Error,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum MetabobError {
+    #[error("RPC request failed")]
+    Rpc(#[from] ClientError),
+
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+
+    #[error("couldn't access {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("couldn't read or write json file {}", path.display())]
+    Json {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    // The keypair reader's error isn't Send, so only its message survives
+    #[error("couldn't read keypair file {path}: {message}")]
+    Keypair { path: String, message: String },
+
+    #[error("{message}")]
+    Config {
+        message: String,
+        #[source]
+        source: Option<Box<dyn StdError + Send + Sync>>,
+    },
+
+    #[error("invalid pubkey {value}")]
+    InvalidPubkey {
+        value: String,
+        #[source]
+        source: ParsePubkeyError,
+    },
+
+    #[error("{0}")]
+    Validation(String),
+
+    #[error("database error in {}", path.display())]
+    Database {
+        path: PathBuf,
+        #[source]
+        source: rusqlite::Error,
+    },
+}
+
+impl MetabobError {
+    pub fn io<P: Into<PathBuf>>(path: P) -> impl FnOnce(io::Error) -> MetabobError {
+        let path = path.into();
+        move |source| MetabobError::Io { path, source }
+    }
+
+    pub fn json<P: Into<PathBuf>>(path: P) -> impl FnOnce(serde_json::Error) -> MetabobError {
+        let path = path.into();
+        move |source| MetabobError::Json { path, source }
+    }
+
+    pub fn db<P: Into<PathBuf>>(path: P) -> impl FnOnce(rusqlite::Error) -> MetabobError {
+        let path = path.into();
+        move |source| MetabobError::Database { path, source }
+    }
+
+    pub fn config<S: Into<String>>(message: S) -> MetabobError {
+        MetabobError::Config {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn config_with_source<S, E>(message: S, source: E) -> MetabobError
+    where
+        S: Into<String>,
+        E: StdError + Send + Sync + 'static,
+    {
+        MetabobError::Config {
+            message: message.into(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    /// Exit codes for the CLI: 3 rpc, 4 decode, 5 file io, 6 keypair, 7 config, 8 invalid input,
+    /// 9 database
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MetabobError::Rpc(_) => 3,
+            MetabobError::Decode(_) => 4,
+            MetabobError::Io { .. } | MetabobError::Json { .. } => 5,
+            MetabobError::Keypair { .. } => 6,
+            MetabobError::Config { .. } => 7,
+            MetabobError::InvalidPubkey { .. } | MetabobError::Validation(_) => 8,
+            MetabobError::Database { .. } => 9,
+        }
+    }
+
+    /// The error and everything under it on one line, skipping causes that the message
+    /// above already repeats
+    pub fn report(&self) -> String {
+        let mut report = self.to_string();
+        let mut source = self.source();
+        while let Some(cause) = source {
+            let cause_message = cause.to_string();
+            if !report.contains(&cause_message) {
+                report = format!("{}: {}", report, cause_message);
+            }
+            source = cause.source();
+        }
+        report
+    }
 }
@@ -0,0 +1,423 @@
+use crate::constants::DEFAULT_ALLOWLIST;
+use crate::errors::Result;
+use crate::observer::Observer;
+use crate::spl::{AccountStruct, TokenListEntry};
+use crate::status;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// Why an entry was kept out of the token list
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasonCode {
+    EmptyName,
+    EmptySymbol,
+    GarbageName,
+    GarbageSymbol,
+    ControlCharacters,
+    Impersonation,
+    ZeroSupply,
+    MintAuthorityActive,
+    /// The mint was saved before its authorities were kept, so there's no telling
+    AuthorityUnknown,
+}
+
+impl ReasonCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReasonCode::EmptyName => "empty_name",
+            ReasonCode::EmptySymbol => "empty_symbol",
+            ReasonCode::GarbageName => "garbage_name",
+            ReasonCode::GarbageSymbol => "garbage_symbol",
+            ReasonCode::ControlCharacters => "control_characters",
+            ReasonCode::Impersonation => "impersonation",
+            ReasonCode::ZeroSupply => "zero_supply",
+            ReasonCode::MintAuthorityActive => "mint_authority_active",
+            ReasonCode::AuthorityUnknown => "authority_unknown",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Reason {
+    pub code: ReasonCode,
+    pub message: String,
+}
+
+impl Reason {
+    fn new<S: Into<String>>(code: ReasonCode, message: S) -> Reason {
+        Reason {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// An entry the filter kept out, with everything that was wrong with it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rejection {
+    #[serde(flatten)]
+    pub entry: TokenListEntry,
+    pub reasons: Vec<Reason>,
+}
+
+/// Which checks the filter runs. They're all on by default
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterRules {
+    /// Name or symbol is empty or only whitespace
+    pub empty_names: bool,
+    /// Name or symbol has no letters or digits, or has bytes that weren't valid UTF-8
+    pub garbage_names: bool,
+    /// Control characters anywhere in the name or symbol, past the NUL padding we trim
+    pub control_characters: bool,
+    /// Symbol matches an allowlisted symbol but the mint isn't the allowlisted one
+    pub impersonation: bool,
+    pub zero_supply: bool,
+    /// Someone can still mint more. Mints saved without their authorities are rejected as
+    /// authority_unknown instead of passing
+    pub mint_authority_active: bool,
+    /// Symbol to the one mint allowed to use it. Allowlisted mints skip every check
+    pub allowlist: BTreeMap<String, String>,
+}
+
+impl Default for FilterRules {
+    fn default() -> Self {
+        FilterRules {
+            empty_names: true,
+            garbage_names: true,
+            control_characters: true,
+            impersonation: true,
+            zero_supply: true,
+            mint_authority_active: true,
+            allowlist: DEFAULT_ALLOWLIST
+                .iter()
+                .map(|(symbol, address)| (symbol.to_string(), address.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl FilterRules {
+    /// Everything the rules find wrong with a token. Empty if it's fine
+    pub fn check(&self, account: &AccountStruct, entry: &TokenListEntry) -> Vec<Reason> {
+        let mut reasons = Vec::new();
+        if self
+            .allowlist
+            .values()
+            .any(|address| *address == entry.address)
+        {
+            return reasons;
+        }
+
+        for (field, value, empty, garbage) in [
+            (
+                "name",
+                &entry.name,
+                ReasonCode::EmptyName,
+                ReasonCode::GarbageName,
+            ),
+            (
+                "symbol",
+                &entry.symbol,
+                ReasonCode::EmptySymbol,
+                ReasonCode::GarbageSymbol,
+            ),
+        ] {
+            if value.trim().is_empty() {
+                if self.empty_names {
+                    reasons.push(Reason::new(empty, format!("{} is empty", field)));
+                }
+                continue;
+            }
+            if self.garbage_names
+                && (value.contains(char::REPLACEMENT_CHARACTER)
+                    || !value.chars().any(char::is_alphanumeric))
+            {
+                reasons.push(Reason::new(
+                    garbage,
+                    format!(
+                        "{} {:?} has no letters or digits or isn't valid UTF-8",
+                        field, value
+                    ),
+                ));
+            }
+        }
+        if self.control_characters
+            && (entry.name.chars().any(char::is_control)
+                || entry.symbol.chars().any(char::is_control))
+        {
+            reasons.push(Reason::new(
+                ReasonCode::ControlCharacters,
+                "name or symbol has control characters",
+            ));
+        }
+        if self.impersonation {
+            let symbol = normalize_symbol(&entry.symbol);
+            let real = self
+                .allowlist
+                .iter()
+                .find(|(allowed, _)| !symbol.is_empty() && normalize_symbol(allowed) == symbol);
+            if let Some((allowed, address)) = real {
+                reasons.push(Reason::new(
+                    ReasonCode::Impersonation,
+                    format!("uses the symbol of {} ({})", allowed, address),
+                ));
+            }
+        }
+        if self.zero_supply && account.mint.mint.supply == 0 {
+            reasons.push(Reason::new(ReasonCode::ZeroSupply, "supply is zero"));
+        }
+        let mint = &account.mint.mint;
+        match (
+            self.mint_authority_active,
+            mint.has_authorities,
+            mint.mint_authority,
+        ) {
+            (false, _, _) | (true, true, None) => {}
+            (true, false, _) => reasons.push(Reason::new(
+                ReasonCode::AuthorityUnknown,
+                "mint authority unknown, get_mints again to fetch it",
+            )),
+            (true, true, Some(authority)) => reasons.push(Reason::new(
+                ReasonCode::MintAuthorityActive,
+                format!("{} can still mint more", authority),
+            )),
+        }
+        reasons
+    }
+}
+
+/// How many entries made it through and how often each rule rejected one
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FilterCounts {
+    pub accepted: usize,
+    pub rejected: usize,
+    pub reasons: BTreeMap<ReasonCode, usize>,
+}
+
+/// Turns each account into a token list entry and runs it past `rules`, handing entries that
+/// pass to `accepted` and the rest to `rejected` with their reasons
+pub fn filter_token_entries<I, F, G>(
+    accounts: I,
+    rules: &FilterRules,
+    observer: &dyn Observer,
+    mut accepted: F,
+    mut rejected: G,
+) -> Result<FilterCounts>
+where
+    I: IntoIterator<Item = Result<AccountStruct>>,
+    F: FnMut(TokenListEntry) -> Result<()>,
+    G: FnMut(Rejection) -> Result<()>,
+{
+    status!(observer, "Filtering token list...");
+    let mut counts = FilterCounts::default();
+    for account in accounts {
+        let account = account?;
+        let entry = TokenListEntry::from(account.clone());
+        let reasons = rules.check(&account, &entry);
+        if reasons.is_empty() {
+            counts.accepted += 1;
+            accepted(entry)?;
+        } else {
+            counts.rejected += 1;
+            for reason in &reasons {
+                *counts.reasons.entry(reason.code).or_default() += 1;
+            }
+            rejected(Rejection { entry, reasons })?;
+        }
+    }
+
+    status!(
+        observer,
+        "Kept {} tokens and rejected {}.",
+        counts.accepted,
+        counts.rejected
+    );
+    for (code, count) in &counts.reasons {
+        status!(observer, "  {}: {}", code.as_str(), count);
+    }
+    observer.event(
+        "filter",
+        json!({ "accepted": counts.accepted, "rejected": counts.rejected, "reasons": counts.reasons }),
+    );
+    Ok(counts)
+}
+
+/// Uppercase with anything but letters and digits dropped, so `usdc` and `U.S.D.C` both look
+/// like USDC
+fn normalize_symbol(symbol: &str) -> String {
+    symbol
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spl::{MetadataInfo, MetadataStruct, MintInfo, MintStruct};
+    use solana_sdk::pubkey::Pubkey;
+    use std::str::FromStr;
+
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+    fn account(mint_pubkey: Pubkey, name: &str, symbol: &str) -> AccountStruct {
+        AccountStruct::new(
+            MintInfo {
+                mint_pubkey,
+                mint: MintStruct {
+                    supply: 1_000,
+                    decimals: 6,
+                    mint_authority: None,
+                    freeze_authority: None,
+                    is_initialized: true,
+                    has_authorities: true,
+                },
+                slot: 42,
+            },
+            MetadataInfo {
+                metadata_pubkey: Pubkey::new_unique(),
+                metadata: MetadataStruct {
+                    mint: mint_pubkey,
+                    name: name.to_string(),
+                    symbol: symbol.to_string(),
+                    uri: String::new(),
+                },
+                slot: 42,
+            },
+        )
+    }
+
+    fn token(name: &str, symbol: &str) -> AccountStruct {
+        account(Pubkey::new_unique(), name, symbol)
+    }
+
+    fn codes(account: &AccountStruct) -> Vec<ReasonCode> {
+        let entry = TokenListEntry::from(account.clone());
+        FilterRules::default()
+            .check(account, &entry)
+            .into_iter()
+            .map(|reason| reason.code)
+            .collect()
+    }
+
+    #[test]
+    fn good_token_passes() {
+        assert!(codes(&token("Good Token", "GOOD")).is_empty());
+    }
+
+    #[test]
+    fn empty_name_and_symbol() {
+        assert_eq!(
+            codes(&token(" ", "")),
+            vec![ReasonCode::EmptyName, ReasonCode::EmptySymbol]
+        );
+    }
+
+    #[test]
+    fn garbage_name_and_symbol() {
+        assert_eq!(
+            codes(&token("!!!", "\u{fffd}X")),
+            vec![ReasonCode::GarbageName, ReasonCode::GarbageSymbol]
+        );
+    }
+
+    #[test]
+    fn control_characters() {
+        assert_eq!(
+            codes(&token("Bad\u{7}Token", "BAD")),
+            vec![ReasonCode::ControlCharacters]
+        );
+    }
+
+    #[test]
+    fn impersonation() {
+        assert_eq!(
+            codes(&token("Fake USDC", "u.s.d.c")),
+            vec![ReasonCode::Impersonation]
+        );
+    }
+
+    #[test]
+    fn zero_supply() {
+        let mut account = token("Good Token", "GOOD");
+        account.mint.mint.supply = 0;
+        assert_eq!(codes(&account), vec![ReasonCode::ZeroSupply]);
+    }
+
+    #[test]
+    fn mint_authority_active() {
+        let mut account = token("Good Token", "GOOD");
+        account.mint.mint.mint_authority = Some(Pubkey::new_unique());
+        assert_eq!(codes(&account), vec![ReasonCode::MintAuthorityActive]);
+    }
+
+    #[test]
+    fn authority_unknown() {
+        let mut account = token("Good Token", "GOOD");
+        account.mint.mint.has_authorities = false;
+        assert_eq!(codes(&account), vec![ReasonCode::AuthorityUnknown]);
+
+        let rules = FilterRules {
+            mint_authority_active: false,
+            ..FilterRules::default()
+        };
+        let entry = TokenListEntry::from(account.clone());
+        assert!(rules.check(&account, &entry).is_empty());
+    }
+
+    #[test]
+    fn allowlisted_mint_skips_every_check() {
+        let mut account = account(Pubkey::from_str(USDC).unwrap(), "", "USDC");
+        account.mint.mint.mint_authority = Some(Pubkey::new_unique());
+        account.mint.mint.supply = 0;
+        assert!(codes(&account).is_empty());
+    }
+
+    #[test]
+    fn disabled_rules_are_skipped() {
+        let rules = FilterRules {
+            empty_names: false,
+            zero_supply: false,
+            ..FilterRules::default()
+        };
+        let mut account = token("", "GOOD");
+        account.mint.mint.supply = 0;
+        let entry = TokenListEntry::from(account.clone());
+        assert!(rules.check(&account, &entry).is_empty());
+    }
+
+    #[test]
+    fn filter_counts_each_reason() {
+        let accounts = vec![
+            Ok(token("Good Token", "GOOD")),
+            Ok(token("", "")),
+            Ok(token("Fake USDC", "USDC")),
+        ];
+        let mut accepted = vec![];
+        let mut rejected = vec![];
+        let counts = filter_token_entries(
+            accounts,
+            &FilterRules::default(),
+            &crate::observer::Silent,
+            |entry| {
+                accepted.push(entry);
+                Ok(())
+            },
+            |rejection| {
+                rejected.push(rejection);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!((counts.accepted, counts.rejected), (1, 2));
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(rejected.len(), 2);
+        assert_eq!(counts.reasons[&ReasonCode::EmptyName], 1);
+        assert_eq!(counts.reasons[&ReasonCode::EmptySymbol], 1);
+        assert_eq!(counts.reasons[&ReasonCode::Impersonation], 1);
+    }
+}
@@ -1,82 +1,750 @@
-use anyhow::Result;
-use indicatif::ParallelProgressIterator;
-use rand::Rng;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use serde::{Deserialize, Serialize};
-use solana_program::pubkey::Pubkey;
-use solana_sdk::signature::Keypair;
-use solana_sdk::signer::Signer;
+use crate::constants::GUMDROP_PROGRAM_ID;
+use crate::decode::decode_mint_account;
+use crate::errors::{MetabobError, Result};
+use crate::merkle::{verify_proof, MerkleTree};
+use crate::observer::Observer;
+use crate::parse::parse_pubkey;
+use crate::rpc::RpcPool;
+use crate::sink::{save_json, save_json_pretty, OutputSink};
+use crate::status;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::json;
+use solana_program::{hash::hash, pubkey::Pubkey};
+use std::collections::HashSet;
 use std::fs::OpenOptions;
+use std::io::BufReader;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Whitelist {
     pub handle: String,
-    pub amount: u64,
+    #[serde(flatten)]
+    pub claim: Claim,
 }
 
 impl Whitelist {
-    pub fn new(handle: String, amount: u64) -> Whitelist {
-        Whitelist { handle, amount }
+    pub fn new(handle: String, claim: Claim) -> Whitelist {
+        Whitelist { handle, claim }
     }
 }
 
+/// Which gumdrop claim mode a distribution is for
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClaimType {
+    Token,
+    Edition,
+    Candy,
+}
+
+impl FromStr for ClaimType {
+    type Err = MetabobError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "token" | "fungible" => Ok(ClaimType::Token),
+            "edition" => Ok(ClaimType::Edition),
+            "candy" | "candy_machine" | "candy-machine" => Ok(ClaimType::Candy),
+            _ => Err(MetabobError::Validation(format!(
+                "Unknown claim type {}. Expected token, edition or candy",
+                s
+            ))),
+        }
+    }
+}
+
+/// What a recipient gets. Serializes to the shape the gumdrop CLI reads for each mode:
+/// `amount` of tokens, an `edition` number of a master edition, or an `amount` of candy
+/// machine mints. Token and candy claims look the same on disk, so anything with an amount
+/// reads back as a token claim until [`Claim::with_type`] says otherwise
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Claim {
+    Edition {
+        edition: u64,
+    },
+    Token {
+        amount: u64,
+    },
+    #[serde(skip_deserializing)]
+    Candy {
+        amount: u64,
+    },
+}
+
+impl Claim {
+    pub fn new(claim_type: ClaimType, value: u64) -> Claim {
+        match claim_type {
+            ClaimType::Token => Claim::Token { amount: value },
+            ClaimType::Edition => Claim::Edition { edition: value },
+            ClaimType::Candy => Claim::Candy { amount: value },
+        }
+    }
+
+    pub fn claim_type(&self) -> ClaimType {
+        match self {
+            Claim::Token { .. } => ClaimType::Token,
+            Claim::Edition { .. } => ClaimType::Edition,
+            Claim::Candy { .. } => ClaimType::Candy,
+        }
+    }
+
+    /// The amount or edition number, which is the last thing hashed into the leaf
+    pub fn value(&self) -> u64 {
+        match self {
+            Claim::Token { amount } | Claim::Candy { amount } => *amount,
+            Claim::Edition { edition } => *edition,
+        }
+    }
+
+    pub fn with_type(self, claim_type: ClaimType) -> Result<Claim> {
+        match (self, claim_type) {
+            (Claim::Edition { .. }, ClaimType::Edition) => Ok(self),
+            (Claim::Token { amount } | Claim::Candy { amount }, ClaimType::Token)
+            | (Claim::Token { amount } | Claim::Candy { amount }, ClaimType::Candy) => {
+                Ok(Claim::new(claim_type, amount))
+            }
+            _ => Err(MetabobError::Validation(format!(
+                "Expected a {:?} claim but found {:?}",
+                claim_type, self
+            ))),
+        }
+    }
+}
+
+/// Reads a distribution file and makes sure every claim in it is the expected type
+pub fn read_distribution(path: &str, claim_type: ClaimType) -> Result<Vec<Whitelist>> {
+    let whitelist = read_whitelist(path)?;
+    whitelist
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let claim = entry.claim.with_type(claim_type).map_err(|e| {
+                MetabobError::Validation(format!("Bad claim at index {} of {}: {}", i, path, e))
+            })?;
+            Ok(Whitelist::new(entry.handle, claim))
+        })
+        .collect()
+}
+
+/// Reads a distribution file as is, without checking the claims
+pub fn read_whitelist(path: &str) -> Result<Vec<Whitelist>> {
+    read_json(path)
+}
+
+/// Reads a json list of handles, checking each one is the right shape
+pub fn read_handles(path: &str, handle_type: HandleType) -> Result<Vec<String>> {
+    let handles: Vec<String> = read_json(path)?;
+    if let Some(handle) = handles.iter().find(|h| !handle_type.is_valid(h)) {
+        return Err(MetabobError::Validation(format!(
+            "Invalid {:?} handle in {}: {}",
+            handle_type, path, handle
+        )));
+    }
+    Ok(handles)
+}
+
+pub fn read_claim(path: &str) -> Result<ClaimProof> {
+    read_json(path)
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &str) -> Result<T> {
+    let file = OpenOptions::new()
+        .read(true)
+        .open(path)
+        .map_err(MetabobError::io(path))?;
+    serde_json::from_reader(BufReader::new(file)).map_err(MetabobError::json(path))
+}
+
+/// What kind of handle a distribution is keyed by. Anything other than a pubkey is claimed
+/// through the gumdrop OTP flow, so the claimant secret is derived instead of being the handle
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HandleType {
+    Pubkey,
+    Email,
+    Discord,
+    Twitter,
+}
+
+impl FromStr for HandleType {
+    type Err = MetabobError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "pubkey" | "wallet" => Ok(HandleType::Pubkey),
+            "email" => Ok(HandleType::Email),
+            "discord" => Ok(HandleType::Discord),
+            "twitter" => Ok(HandleType::Twitter),
+            _ => Err(MetabobError::Validation(format!(
+                "Unknown handle type {}. Expected pubkey, email, discord or twitter",
+                s
+            ))),
+        }
+    }
+}
+
+impl HandleType {
+    pub fn uses_otp(&self) -> bool {
+        *self != HandleType::Pubkey
+    }
+
+    pub fn is_valid(&self, handle: &str) -> bool {
+        match self {
+            HandleType::Pubkey => Pubkey::from_str(handle).is_ok(),
+            HandleType::Email => {
+                let mut parts = handle.split('@');
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some(local), Some(domain), None) => {
+                        !local.is_empty()
+                            && domain.contains('.')
+                            && !domain.starts_with('.')
+                            && !domain.ends_with('.')
+                            && handle.len() <= 254
+                            && !handle.chars().any(|c| c.is_whitespace() || c.is_control())
+                    }
+                    _ => false,
+                }
+            }
+            // Discord user ids are snowflakes, which is what the gumdrop bot DMs
+            HandleType::Discord => {
+                (17..=20).contains(&handle.len()) && handle.chars().all(|c| c.is_ascii_digit())
+            }
+            HandleType::Twitter => {
+                let name = handle.strip_prefix('@').unwrap_or(handle);
+                (1..=15).contains(&name.len())
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            }
+        }
+    }
+
+    /// Random filler handle of the right shape
+    fn random(&self, rng: &mut StdRng) -> String {
+        let mut name = |len: usize| -> String {
+            (0..len)
+                .map(|_| char::from(b"abcdefghijklmnopqrstuvwxyz0123456789"[rng.gen_range(0..36)]))
+                .collect()
+        };
+        match self {
+            HandleType::Pubkey => random_pubkey(rng).to_string(),
+            HandleType::Email => format!("{}@example.com", name(12)),
+            HandleType::Discord => rng
+                .gen_range(100_000_000_000_000_000u64..10_000_000_000_000_000_000)
+                .to_string(),
+            HandleType::Twitter => format!("x{}", name(14)),
+        }
+    }
+}
+
+/// Makes `repeat` test distributions of `number` recipients, each with one of `handles` (or a
+/// random one once they run out) at a random position. Saves them to the sink and returns
+/// their names. Edition claims are numbered on from `start_edition` (1 by default) across
+/// every list, so lists made together can be merged into one drop
+#[allow(clippy::too_many_arguments)]
 pub fn make_list(
     number: u64,
-    amount: u64,
+    amount: Option<u64>,
+    start_edition: Option<u64>,
     repeat: Option<u64>,
-    pubkey_path: Option<String>,
-) -> Result<()> {
-    let pubkey_vec: Vec<String> = pubkey_path.map_or_else(Vec::new, |path| {
-        let file = OpenOptions::new().read(true).open(path);
-        if let Ok(file) = file {
-            serde_json::from_reader(file).unwrap()
-        } else {
-            vec![]
-        }
-    });
-
-    let mut pubkey_iter = pubkey_vec.into_iter();
+    handles: Vec<String>,
+    handle_type: HandleType,
+    claim_type: ClaimType,
+    seed: Option<u64>,
+    observer: &dyn Observer,
+    sink: &dyn OutputSink,
+) -> Result<Vec<String>> {
+    if number == 0 {
+        return Err(MetabobError::Validation(String::from(
+            "A list needs at least one recipient",
+        )));
+    }
+    let amount = match (claim_type, amount) {
+        (ClaimType::Edition, Some(_)) => {
+            return Err(MetabobError::Validation(String::from(
+                "Edition claims are one edition each, so they don't take an amount",
+            )))
+        }
+        (ClaimType::Edition, None) => 0,
+        (_, Some(amount)) => amount,
+        (_, None) => {
+            return Err(MetabobError::Validation(format!(
+                "An amount is needed for {:?} claims",
+                claim_type
+            )))
+        }
+    };
+    let mut next_edition = match (claim_type, start_edition) {
+        (ClaimType::Edition, Some(0)) => {
+            return Err(MetabobError::Validation(String::from(
+                "Editions are numbered from 1",
+            )))
+        }
+        (ClaimType::Edition, start) => start.unwrap_or(1),
+        (_, Some(_)) => {
+            return Err(MetabobError::Validation(format!(
+                "A start edition is only used for Edition claims, not {:?}",
+                claim_type
+            )))
+        }
+        (_, None) => 0,
+    };
+
+    if let Some(handle) = handles.iter().find(|h| !handle_type.is_valid(h)) {
+        return Err(MetabobError::Validation(format!(
+            "Invalid {:?} handle: {}",
+            handle_type, handle
+        )));
+    }
+
+    // Everything random comes from this one rng so a seeded run can be regenerated exactly
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let mut saved = vec![];
+    let mut handle_iter = handles.into_iter();
     for i in 0..repeat.map_or_else(|| 1, |n| n) {
-        let whitelist_list: Arc<Mutex<Vec<Whitelist>>> = Arc::new(Mutex::new(vec![]));
-        let pubkey = pubkey_iter.next();
-        let new_pubkey = pubkey.map_or_else(
-            || Keypair::new().pubkey(),
-            |key| Pubkey::from_str(&key).unwrap_or_else(|_| Keypair::new().pubkey()),
-        );
-        (0..(number as usize - 1))
-            .into_par_iter()
-            .progress()
-            .for_each(|_| {
-                let whitelist_list = Arc::clone(&whitelist_list);
-                let handle = Keypair::new().pubkey().to_string();
-                let new_whitelist = Whitelist::new(handle, amount);
-                whitelist_list.lock().unwrap().push(new_whitelist);
-            });
-
-        let index = rand::thread_rng().gen_range(0..number as usize);
-
-        whitelist_list
-            .lock()
-            .unwrap()
-            .insert(index, Whitelist::new(new_pubkey.to_string(), amount));
-        let whitelist_file = OpenOptions::new()
-            .create_new(true)
-            .write(true)
-            .open(format!("./distribution{}-{}.json", number, i))
-            .expect("Couldn't make new file");
-        println!("Saving whitelist file #{}...", i);
+        let new_handle = handle_iter
+            .next()
+            .unwrap_or_else(|| handle_type.random(&mut rng));
+        let progress = observer.progress("make_list", number - 1);
+        let mut handles: Vec<String> = (0..(number as usize - 1))
+            .inspect(|_| progress.inc(1))
+            .map(|_| handle_type.random(&mut rng))
+            .collect();
+        progress.finish();
+
+        let index = rng.gen_range(0..number as usize);
+        handles.insert(index, new_handle);
+
+        // Every edition claim needs its own edition number, handed out in list order and
+        // carrying on from the last list
+        let whitelist_list: Vec<Whitelist> = handles
+            .into_iter()
+            .map(|handle| match claim_type {
+                ClaimType::Edition => {
+                    next_edition += 1;
+                    Whitelist::new(handle, Claim::new(claim_type, next_edition - 1))
+                }
+                _ => Whitelist::new(handle, Claim::new(claim_type, amount)),
+            })
+            .collect();
+        let name = format!("distribution{}-{}.json", number, i);
+        status!(observer, "Saving whitelist file #{}...", i);
         let start = Instant::now();
-        serde_json::to_writer(whitelist_file, &*whitelist_list.lock().unwrap())?;
+        save_json(sink, &name, &whitelist_list)?;
         let duration = start.elapsed();
-        println!(
+        status!(
+            observer,
             "Saved whitelist file #{} in {} minutes and {} seconds!",
             i,
             duration.as_secs() / 60,
             duration.as_secs() % 60
         );
+        observer.event(
+            "saved",
+            json!({ "file": name, "entries": whitelist_list.len() }),
+        );
+        saved.push(name);
+    }
+    Ok(saved)
+}
+
+/// Filler wallets only need to look like pubkeys, so there's no point paying for a keypair
+fn random_pubkey(rng: &mut StdRng) -> Pubkey {
+    Pubkey::new_from_array(rng.gen())
+}
+
+/// A single recipient's claim, with everything needed to check it against the merkle root.
+/// The target is the token mint, the master edition mint or the candy machine, depending on
+/// the claim type
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaimProof {
+    pub handle: String,
+    pub claim_type: ClaimType,
+    #[serde(flatten)]
+    pub claim: Claim,
+    pub index: u64,
+    pub secret: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<u32>,
+    pub target: String,
+    pub root: String,
+    pub proof: Vec<String>,
+}
+
+impl ClaimProof {
+    fn leaf(&self) -> Result<Vec<u8>> {
+        let secret = parse_pubkey(&self.secret)?;
+        let target = parse_pubkey(&self.target)?;
+        let claim = self.claim.with_type(self.claim_type)?;
+        Ok(claim_leaf(self.index, &secret, &target, &claim))
+    }
+}
+
+/// The key a recipient proves ownership of when claiming
+#[derive(Debug, Clone, Copy)]
+pub struct Claimant {
+    pub secret: Pubkey,
+    pub pin: Option<u32>,
+}
+
+/// Wallet handles claim with their own key. OTP handles get a random pin, and the secret is a
+/// PDA of the distributor base, the handle and that pin, so it can't be guessed from the handle
+pub fn claimant_secrets(
+    whitelist: &[Whitelist],
+    handle_type: HandleType,
+    base: Option<&Pubkey>,
+) -> Result<Vec<Claimant>> {
+    let mut rng = StdRng::from_entropy();
+    whitelist
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            if !handle_type.is_valid(&entry.handle) {
+                return Err(MetabobError::Validation(format!(
+                    "Invalid {:?} handle at index {}: {}",
+                    handle_type, i, entry.handle
+                )));
+            }
+            if !handle_type.uses_otp() {
+                return Ok(Claimant {
+                    secret: parse_pubkey(&entry.handle)?,
+                    pin: None,
+                });
+            }
+            let base = base.ok_or_else(|| {
+                MetabobError::Validation(format!(
+                    "A distributor base pubkey is needed for {:?} handles",
+                    handle_type
+                ))
+            })?;
+            let pin: u32 = rng.gen_range(0..1_000_000);
+            let (secret, _) = Pubkey::find_program_address(
+                &[
+                    base.as_ref(),
+                    &hash(entry.handle.as_bytes()).to_bytes(),
+                    &pin.to_le_bytes(),
+                ],
+                &GUMDROP_PROGRAM_ID,
+            );
+            Ok(Claimant {
+                secret,
+                pin: Some(pin),
+            })
+        })
+        .collect()
+}
+
+/// Leaf data in the same layout the gumdrop program hashes on claim: index, claimant secret,
+/// target, then the amount or edition number
+pub fn claim_leaf(index: u64, secret: &Pubkey, target: &Pubkey, claim: &Claim) -> Vec<u8> {
+    [
+        &index.to_le_bytes()[..],
+        secret.as_ref(),
+        target.as_ref(),
+        &claim.value().to_le_bytes()[..],
+    ]
+    .concat()
+}
+
+pub fn build_tree(whitelist: &[Whitelist], claimants: &[Claimant], target: &Pubkey) -> MerkleTree {
+    let leaves: Vec<Vec<u8>> = whitelist
+        .iter()
+        .zip(claimants)
+        .enumerate()
+        .map(|(i, (entry, claimant))| claim_leaf(i as u64, &claimant.secret, target, &entry.claim))
+        .collect();
+    MerkleTree::new(&leaves)
+}
+
+/// Builds the merkle tree for a distribution and saves `root.json` plus a `claim-{i}.json`
+/// proof per recipient to the sink. Returns the root
+pub fn make_claims(
+    whitelist: &[Whitelist],
+    target: &Pubkey,
+    handle_type: HandleType,
+    claim_type: ClaimType,
+    base: Option<&Pubkey>,
+    observer: &dyn Observer,
+    sink: &dyn OutputSink,
+) -> Result<String> {
+    if whitelist.is_empty() {
+        return Err(MetabobError::Validation(String::from(
+            "Distribution has no recipients",
+        )));
+    }
+
+    status!(
+        observer,
+        "Building merkle tree of {} claims...",
+        whitelist.len()
+    );
+    let claimants = claimant_secrets(whitelist, handle_type, base)?;
+    let tree = build_tree(whitelist, &claimants, target);
+    let root = bs58::encode(tree.root()).into_string();
+    status!(observer, "Merkle root: {}", root);
+
+    save_json_pretty(
+        sink,
+        "root.json",
+        &json!({
+            "root": root,
+            "target": target.to_string(),
+            "claimType": claim_type,
+            "handleType": handle_type,
+            "base": base.map(|base| base.to_string()),
+            "recipients": whitelist.len(),
+        }),
+    )?;
+
+    status!(observer, "Saving claim files...");
+    let progress = observer.progress("build_tree", whitelist.len() as u64);
+    whitelist
+        .par_iter()
+        .zip(claimants.par_iter())
+        .enumerate()
+        .inspect(|_| progress.inc(1))
+        .try_for_each(|(i, (entry, claimant))| -> Result<()> {
+            let claim = ClaimProof {
+                handle: entry.handle.clone(),
+                claim_type,
+                claim: entry.claim,
+                index: i as u64,
+                secret: claimant.secret.to_string(),
+                pin: claimant.pin,
+                target: target.to_string(),
+                root: root.clone(),
+                proof: tree
+                    .proof(i)
+                    .iter()
+                    .map(|node| bs58::encode(node).into_string())
+                    .collect(),
+            };
+            save_json_pretty(sink, &format!("claim-{}.json", i), &claim)
+        })?;
+    progress.finish();
+    status!(observer, "Saved {} claim files!", whitelist.len());
+    observer.event("tree", json!({ "root": root, "claims": whitelist.len() }));
+    Ok(root)
+}
+
+/// Checks a claim's proof against `root`, or the root saved in the claim if there isn't one
+pub fn verify_claim(
+    claim: &ClaimProof,
+    root: Option<&str>,
+    observer: &dyn Observer,
+) -> Result<bool> {
+    let root: [u8; 32] = decode_node(root.unwrap_or(&claim.root))?;
+    let proof = claim
+        .proof
+        .iter()
+        .map(|node| decode_node(node))
+        .collect::<Result<Vec<_>>>()?;
+
+    let valid = verify_proof(&proof, &root, &claim.leaf()?);
+    if valid {
+        status!(
+            observer,
+            "Proof for claim #{} ({}) is valid!",
+            claim.index,
+            claim.handle
+        );
+    } else {
+        status!(
+            observer,
+            "Proof for claim #{} ({}) does NOT match the root!",
+            claim.index,
+            claim.handle
+        );
+    }
+    observer.event(
+        "proof",
+        json!({ "index": claim.index, "handle": claim.handle, "valid": valid }),
+    );
+    Ok(valid)
+}
+
+fn decode_node(node: &str) -> Result<[u8; 32]> {
+    bs58::decode(node)
+        .into_vec()
+        .map_err(|e| MetabobError::Validation(format!("Merkle node {} isn't base58: {}", node, e)))?
+        .try_into()
+        .map_err(|_| MetabobError::Validation(format!("Merkle node {} is not 32 bytes", node)))
+}
+
+/// Everything wrong (or worth knowing) about a distribution file
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub recipients: usize,
+    #[serde(serialize_with = "serialize_total")]
+    pub total_amount: u128,
+    pub invalid_handles: Vec<(usize, String)>,
+    pub duplicate_handles: Vec<(usize, String)>,
+    pub zero_amounts: Vec<usize>,
+    pub wrong_claim_types: Vec<usize>,
+    pub duplicate_editions: Vec<(usize, u64)>,
+    pub decimals: Option<u8>,
+    pub supply: Option<u64>,
+    pub budget: Option<u64>,
+}
+
+impl ValidationReport {
+    pub fn over_supply(&self) -> bool {
+        matches!(self.supply, Some(supply) if self.total_amount > supply as u128)
+    }
+
+    pub fn over_budget(&self) -> bool {
+        matches!(self.budget, Some(budget) if self.total_amount > budget as u128)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.invalid_handles.is_empty()
+            && self.duplicate_handles.is_empty()
+            && self.zero_amounts.is_empty()
+            && self.wrong_claim_types.is_empty()
+            && self.duplicate_editions.is_empty()
+            && !self.over_supply()
+            && !self.over_budget()
+    }
+
+    /// Total amount in whole tokens, if we know the mint's decimals
+    pub fn ui_total(&self) -> Option<f64> {
+        self.decimals
+            .map(|decimals| self.total_amount as f64 / 10f64.powi(decimals as i32))
+    }
+}
+
+/// serde_json values can't hold a u128, so totals past u64 go out as a string
+fn serialize_total<S: Serializer>(
+    total: &u128,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match u64::try_from(*total) {
+        Ok(total) => serializer.serialize_u64(total),
+        Err(_) => serializer.serialize_str(&total.to_string()),
+    }
+}
+
+/// For edition drops the total is the number of editions handed out
+pub fn validate_whitelist(
+    whitelist: &[Whitelist],
+    handle_type: HandleType,
+    claim_type: ClaimType,
+) -> ValidationReport {
+    let mut report = ValidationReport {
+        recipients: whitelist.len(),
+        ..Default::default()
+    };
+    let mut seen = HashSet::new();
+    let mut seen_editions = HashSet::new();
+    for (i, entry) in whitelist.iter().enumerate() {
+        if !handle_type.is_valid(&entry.handle) {
+            report.invalid_handles.push((i, entry.handle.clone()));
+        }
+        if !seen.insert(entry.handle.as_str()) {
+            report.duplicate_handles.push((i, entry.handle.clone()));
+        }
+        let claim = match entry.claim.with_type(claim_type) {
+            Ok(claim) => claim,
+            Err(_) => {
+                report.wrong_claim_types.push(i);
+                continue;
+            }
+        };
+        if claim.value() == 0 {
+            report.zero_amounts.push(i);
+        }
+        match claim {
+            Claim::Edition { edition } => {
+                if !seen_editions.insert(edition) {
+                    report.duplicate_editions.push((i, edition));
+                }
+                report.total_amount += 1;
+            }
+            Claim::Token { amount } | Claim::Candy { amount } => {
+                report.total_amount += amount as u128;
+            }
+        }
+    }
+    report
+}
+
+/// Checks a distribution, and against the mint's supply and decimals if one is given
+pub fn validate_list(
+    client: &RpcPool,
+    whitelist: &[Whitelist],
+    handle_type: HandleType,
+    claim_type: ClaimType,
+    mint: Option<&Pubkey>,
+    budget: Option<u64>,
+    observer: &dyn Observer,
+) -> Result<ValidationReport> {
+    status!(observer, "Validating {} entries...", whitelist.len());
+
+    let mut report = validate_whitelist(whitelist, handle_type, claim_type);
+    report.budget = budget;
+    if let Some(mint) = mint {
+        let mint_account = decode_mint_account(&client.get_account(mint)?)?;
+        report.decimals = Some(mint_account.decimals);
+        report.supply = Some(mint_account.supply);
+    }
+    observer.event("validation", json!(report));
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::Silent;
+    use crate::sink::MemorySink;
+
+    fn make(number: u64, seed: Option<u64>, sink: &MemorySink) -> Result<Vec<String>> {
+        make_list(
+            number,
+            None,
+            None,
+            Some(2),
+            vec![],
+            HandleType::Pubkey,
+            ClaimType::Edition,
+            seed,
+            &Silent,
+            sink,
+        )
+    }
+
+    #[test]
+    fn same_seed_makes_identical_lists() {
+        let first = MemorySink::new();
+        let second = MemorySink::new();
+        let names = make(5, Some(7), &first).unwrap();
+        assert_eq!(make(5, Some(7), &second).unwrap(), names);
+        assert_eq!(names.len(), 2);
+        assert_eq!(first.into_files(), second.into_files());
+    }
+
+    #[test]
+    fn different_seeds_make_different_lists() {
+        let first = MemorySink::new();
+        let second = MemorySink::new();
+        make(5, Some(7), &first).unwrap();
+        make(5, Some(8), &second).unwrap();
+        assert_ne!(first.into_files(), second.into_files());
+    }
+
+    #[test]
+    fn empty_list_is_rejected() {
+        let sink = MemorySink::new();
+        assert!(matches!(
+            make(0, Some(7), &sink),
+            Err(MetabobError::Validation(_))
+        ));
+        assert!(sink.into_files().is_empty());
     }
-    Ok(())
 }
@@ -0,0 +1,250 @@
+use crate::constants::{
+    CANDY_MACHINE_V1_PROGRAM_ID, CANDY_MACHINE_V2_PROGRAM_ID, GUMDROP_PROGRAM_ID,
+};
+use crate::decode::{
+    decode_candy_machine, decode_distributor, decode_edition, decode_edition_marker,
+    decode_master_edition, decode_metadata, decode_mint_account, is_anchor_account, CandyMachine,
+    Distributor, Edition, EditionMarker, Key, MasterEdition, Metadata,
+};
+use crate::errors::{DecodeError, MetabobError, Result};
+use crate::pda::{get_edition_pda, get_metadata_pda};
+use crate::rpc::RpcPool;
+use mpl_token_metadata::ID as TOKEN_METADATA_PROGRAM_ID;
+use serde::Serialize;
+use solana_program::{program_option::COption, program_pack::Pack};
+use solana_sdk::{account::Account, commitment_config::CommitmentConfig, pubkey::Pubkey};
+use spl_token::{
+    state::{Account as TokenAccount, Multisig},
+    ID as TOKEN_PROGRAM_ID,
+};
+use std::io;
+
+/// What an account turned out to be, going by its owner and size
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountType {
+    Mint,
+    TokenAccount,
+    Multisig,
+    Metadata,
+    MasterEdition,
+    Edition,
+    EditionMarker,
+    CandyMachine,
+    GumdropDistributor,
+    Unknown,
+    /// Nothing at that address
+    Missing,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AccountSummary {
+    pub owner: String,
+    pub lamports: u64,
+    pub data_len: usize,
+    pub executable: bool,
+}
+
+/// One account, decoded as far as we could. Mints also get their metadata and edition
+#[derive(Serialize, Debug, Clone)]
+pub struct Inspection {
+    pub address: String,
+    #[serde(rename = "type")]
+    pub account_type: AccountType,
+    #[serde(flatten)]
+    pub account: Option<AccountSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<AccountData>,
+    /// Why an account of a type we know didn't decode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Box<Inspection>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edition: Option<Box<Inspection>>,
+}
+
+/// Fetches and decodes an account. If it's a mint, its metadata and edition PDAs are fetched
+/// and decoded along with it
+pub fn inspect(
+    client: &RpcPool,
+    address: &Pubkey,
+    commitment: CommitmentConfig,
+) -> Result<Inspection> {
+    let account = client
+        .get_multiple_accounts_with_commitment(&[*address], commitment)?
+        .value
+        .pop()
+        .flatten()
+        .ok_or_else(|| MetabobError::Validation(format!("There's no account at {}", address)))?;
+    let mut inspection = inspect_account(address, Some(&account));
+    if inspection.account_type == AccountType::Mint {
+        let pdas = [get_metadata_pda(address), get_edition_pda(address)];
+        let mut accounts = client
+            .get_multiple_accounts_with_commitment(&pdas, commitment)?
+            .value
+            .into_iter();
+        inspection.metadata = Some(Box::new(inspect_account(
+            &pdas[0],
+            accounts.next().flatten().as_ref(),
+        )));
+        inspection.edition = Some(Box::new(inspect_account(
+            &pdas[1],
+            accounts.next().flatten().as_ref(),
+        )));
+    }
+    Ok(inspection)
+}
+
+/// Works out what an already fetched account is and decodes it
+pub fn inspect_account(address: &Pubkey, account: Option<&Account>) -> Inspection {
+    let mut inspection = Inspection {
+        address: address.to_string(),
+        account_type: AccountType::Missing,
+        account: None,
+        data: None,
+        error: None,
+        metadata: None,
+        edition: None,
+    };
+    let account = match account {
+        Some(account) => account,
+        None => return inspection,
+    };
+    inspection.account = Some(AccountSummary {
+        owner: account.owner.to_string(),
+        lamports: account.lamports,
+        data_len: account.data.len(),
+        executable: account.executable,
+    });
+    inspection.account_type = account_type(account);
+    match decode(inspection.account_type, account) {
+        Ok(data) => inspection.data = data,
+        Err(e) => inspection.error = Some(e.to_string()),
+    }
+    inspection
+}
+
+pub fn account_type(account: &Account) -> AccountType {
+    let data = account.data.as_slice();
+    if account.owner == TOKEN_PROGRAM_ID {
+        match data.len() {
+            spl_token::state::Mint::LEN => AccountType::Mint,
+            TokenAccount::LEN => AccountType::TokenAccount,
+            Multisig::LEN => AccountType::Multisig,
+            _ => AccountType::Unknown,
+        }
+    } else if account.owner == TOKEN_METADATA_PROGRAM_ID {
+        match data.first().copied().and_then(Key::from_u8) {
+            Some(Key::MetadataV1) => AccountType::Metadata,
+            Some(Key::MasterEditionV1 | Key::MasterEditionV2) => AccountType::MasterEdition,
+            Some(Key::EditionV1) => AccountType::Edition,
+            Some(Key::EditionMarker | Key::EditionMarkerV2) => AccountType::EditionMarker,
+            _ => AccountType::Unknown,
+        }
+    } else if (account.owner == CANDY_MACHINE_V2_PROGRAM_ID
+        || account.owner == CANDY_MACHINE_V1_PROGRAM_ID)
+        && is_anchor_account(data, "CandyMachine")
+    {
+        AccountType::CandyMachine
+    } else if account.owner == GUMDROP_PROGRAM_ID && is_anchor_account(data, "MerkleDistributor") {
+        AccountType::GumdropDistributor
+    } else {
+        AccountType::Unknown
+    }
+}
+
+/// The decoded data of each account type we know
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum AccountData {
+    Mint(MintData),
+    TokenAccount(TokenAccountData),
+    Metadata(Box<Metadata>),
+    MasterEdition(MasterEdition),
+    Edition(Edition),
+    EditionMarker(EditionMarker),
+    CandyMachine(Box<CandyMachine>),
+    GumdropDistributor(Distributor),
+}
+
+/// Amounts are strings, since they go past what JSON numbers can hold exactly
+#[derive(Serialize, Debug, Clone)]
+pub struct MintData {
+    pub mint_authority: Option<String>,
+    pub supply: String,
+    pub decimals: u8,
+    pub is_initialized: bool,
+    pub freeze_authority: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TokenAccountData {
+    pub mint: String,
+    pub owner: String,
+    pub amount: String,
+    pub delegate: Option<String>,
+    /// `uninitialized`, `initialized` or `frozen`
+    pub state: String,
+    /// Lamports kept rent exempt, for wrapped SOL accounts
+    pub is_native: Option<u64>,
+    pub delegated_amount: String,
+    pub close_authority: Option<String>,
+}
+
+fn decode(
+    account_type: AccountType,
+    account: &Account,
+) -> Result<Option<AccountData>, DecodeError> {
+    let data = account.data.as_slice();
+    let decoded = match account_type {
+        AccountType::Mint => {
+            let mint = decode_mint_account(account)?;
+            AccountData::Mint(MintData {
+                mint_authority: optional_key(mint.mint_authority),
+                supply: mint.supply.to_string(),
+                decimals: mint.decimals,
+                is_initialized: mint.is_initialized,
+                freeze_authority: optional_key(mint.freeze_authority),
+            })
+        }
+        AccountType::TokenAccount => {
+            let token =
+                TokenAccount::unpack(data).map_err(|e| DecodeError::DecodeAccountFailed {
+                    kind: "token",
+                    source: io::Error::new(io::ErrorKind::InvalidData, e),
+                })?;
+            AccountData::TokenAccount(TokenAccountData {
+                mint: token.mint.to_string(),
+                owner: token.owner.to_string(),
+                amount: token.amount.to_string(),
+                delegate: optional_key(token.delegate),
+                state: format!("{:?}", token.state).to_lowercase(),
+                is_native: token.is_native.into(),
+                delegated_amount: token.delegated_amount.to_string(),
+                close_authority: optional_key(token.close_authority),
+            })
+        }
+        AccountType::Metadata => AccountData::Metadata(Box::new(decode_metadata(data)?)),
+        AccountType::MasterEdition => AccountData::MasterEdition(decode_master_edition(data)?),
+        AccountType::Edition => AccountData::Edition(decode_edition(data)?),
+        AccountType::EditionMarker => AccountData::EditionMarker(decode_edition_marker(data)?),
+        AccountType::CandyMachine => {
+            let version = if account.owner == CANDY_MACHINE_V1_PROGRAM_ID {
+                1
+            } else {
+                2
+            };
+            AccountData::CandyMachine(Box::new(decode_candy_machine(data, version)?))
+        }
+        AccountType::GumdropDistributor => {
+            AccountData::GumdropDistributor(decode_distributor(data)?)
+        }
+        AccountType::Multisig | AccountType::Unknown | AccountType::Missing => return Ok(None),
+    };
+    Ok(Some(decoded))
+}
+
+fn optional_key(key: COption<Pubkey>) -> Option<String> {
+    Option::from(key).map(|key: Pubkey| key.to_string())
+}
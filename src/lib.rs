@@ -1,10 +1,24 @@
+pub mod config;
 pub mod constants;
+pub mod db;
 pub mod decode;
 pub mod errors;
+pub mod filter;
 pub mod gumdrop;
+pub mod inspect;
 pub mod limiter;
+pub mod logos;
+pub mod merkle;
 pub mod metadata;
+pub mod observer;
 pub mod opt;
+pub mod output;
 pub mod parse;
+pub mod pda;
 pub mod process_subcommands;
+pub mod rpc;
+pub mod runtime;
+pub mod sink;
 pub mod spl;
+pub mod stream;
+pub mod token_list;
@@ -1,16 +1,20 @@
-/// This entire file was taken from metaboss
+/// The limiter itself was taken from metaboss
 use ratelimit::Handle;
-use std::{thread, time::Duration};
+use std::{num::NonZeroU32, thread, time::Duration};
 
 use crate::constants::*;
 
 pub fn create_rate_limiter() -> Handle {
+    create_rate_limiter_with_delay(*RPC_DELAY_NS.read().unwrap())
+}
+
+pub fn create_rate_limiter_with_delay(delay_ns: u32) -> Handle {
     let num_cpus = num_cpus::get();
 
     let mut limiter = ratelimit::Builder::new()
         .capacity(num_cpus as u32)
         .quantum(1)
-        .interval(Duration::new(0, *RPC_DELAY_NS.read().unwrap()))
+        .interval(Duration::new(0, delay_ns))
         .build();
 
     let handle = limiter.make_handle();
@@ -19,3 +23,82 @@ pub fn create_rate_limiter() -> Handle {
     });
     handle
 }
+
+/// Turns on rate limiting for the chosen rpc. An explicit requests per second wins, otherwise
+/// we fall back to the known delay for the rpc's host, otherwise there's no limit at all
+pub fn configure_rate_limit(rpc_url: &str, requests_per_second: Option<NonZeroU32>) {
+    if let Some(delay_ns) = rate_limit_delay_ns(rpc_url, requests_per_second) {
+        *USE_RATE_LIMIT.write().unwrap() = true;
+        *RPC_DELAY_NS.write().unwrap() = delay_ns;
+    }
+}
+
+pub fn rate_limit_delay_ns(rpc_url: &str, requests_per_second: Option<NonZeroU32>) -> Option<u32> {
+    match requests_per_second {
+        Some(rps) => Some(1_000_000_000 / rps.get()),
+        None => RATE_LIMIT_DELAYS
+            .get(rpc_host(rpc_url))
+            .map(|delay_ms| delay_ms * 1_000_000),
+    }
+}
+
+/// Host part of an rpc url, without the scheme, port or path
+pub fn rpc_host(rpc_url: &str) -> &str {
+    let without_scheme = rpc_url.split_once("://").map_or(rpc_url, |(_, rest)| rest);
+    without_scheme
+        .split(['/', ':', '?'])
+        .next()
+        .unwrap_or(without_scheme)
+}
+
+/// One of these is made per run and handed to everything that talks to the rpc. Clones share
+/// the same bucket, so the limit holds across threads
+#[derive(Clone)]
+pub struct RateLimiter {
+    handle: Option<Handle>,
+}
+
+impl RateLimiter {
+    pub fn new() -> RateLimiter {
+        let handle = if *USE_RATE_LIMIT.read().unwrap() {
+            Some(create_rate_limiter())
+        } else {
+            None
+        };
+        RateLimiter { handle }
+    }
+
+    /// A limiter of its own for an extra endpoint, rather than the global one
+    pub fn for_endpoint(rpc_url: &str, requests_per_second: Option<NonZeroU32>) -> RateLimiter {
+        RateLimiter {
+            handle: rate_limit_delay_ns(rpc_url, requests_per_second)
+                .map(create_rate_limiter_with_delay),
+        }
+    }
+
+    pub fn unlimited() -> RateLimiter {
+        RateLimiter { handle: None }
+    }
+
+    pub fn wait(&self) {
+        if let Some(handle) = &self.handle {
+            handle.clone().wait();
+        }
+    }
+
+    /// `wait` for async code, which sleeps between tries instead of blocking the thread
+    pub async fn wait_async(&self) {
+        if let Some(handle) = &self.handle {
+            let mut handle = handle.clone();
+            while handle.try_wait().is_err() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new()
+    }
+}
@@ -0,0 +1,290 @@
+use crate::errors::{MetabobError, Result};
+use crate::observer::Observer;
+use crate::parse::parse_pubkey;
+use crate::runtime::for_each_blocking_in_flight;
+use crate::spl::TokenListEntry;
+use crate::status;
+use image::{imageops::FilterType, ImageFormat};
+use log::warn;
+use reqwest::{blocking::Client, header::CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use solana_program::pubkey::Pubkey;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// How a logo check turned out. Anything but `Ok` gets flagged
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LogoStatus {
+    Ok,
+    /// The request failed or didn't come back 2xx
+    Unreachable,
+    /// Bigger than the size limit
+    Oversized,
+    /// Came back, but isn't an image we could decode
+    NotAnImage,
+    /// The entry's address isn't a pubkey, so it isn't checked. Its thumbnail would be named
+    /// after it
+    InvalidAddress,
+}
+
+impl LogoStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogoStatus::Ok => "ok",
+            LogoStatus::Unreachable => "unreachable",
+            LogoStatus::Oversized => "oversized",
+            LogoStatus::NotAnImage => "not_an_image",
+            LogoStatus::InvalidAddress => "invalid_address",
+        }
+    }
+}
+
+/// What we found at one entry's logo uri
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogoCheck {
+    pub address: String,
+    #[serde(rename = "chainId")]
+    pub chain_id: u8,
+    pub symbol: String,
+    pub logo_uri: String,
+    pub status: LogoStatus,
+    /// As the server sent it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// What the bytes actually are, e.g. png or svg
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// Not known for SVGs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Hex SHA-256 of the bytes downloaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl LogoCheck {
+    fn new(entry: &TokenListEntry) -> LogoCheck {
+        LogoCheck {
+            address: entry.address.clone(),
+            chain_id: entry.chain_id,
+            symbol: entry.symbol.clone(),
+            logo_uri: entry.logo_uri.clone(),
+            status: LogoStatus::Ok,
+            content_type: None,
+            format: None,
+            bytes: None,
+            width: None,
+            height: None,
+            sha256: None,
+            thumbnail: None,
+            error: None,
+        }
+    }
+
+    fn flag<S: Into<String>>(mut self, status: LogoStatus, error: S) -> LogoCheck {
+        self.status = status;
+        self.error = Some(error.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogoOptions {
+    /// Logos bigger than this many bytes are flagged oversized
+    pub max_bytes: u64,
+    pub timeout: Duration,
+    pub in_flight: usize,
+    /// Where to save a PNG thumbnail of each decoded logo, named after its chain id and token
+    /// address
+    pub thumbnail_dir: Option<PathBuf>,
+    /// Thumbnails fit in a square this many pixels wide
+    pub thumbnail_size: u32,
+}
+
+/// How many logos were checked and how many came out each way
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LogoCounts {
+    pub checked: usize,
+    pub statuses: BTreeMap<LogoStatus, usize>,
+}
+
+impl LogoCounts {
+    pub fn flagged(&self) -> usize {
+        self.checked - self.statuses.get(&LogoStatus::Ok).copied().unwrap_or(0)
+    }
+}
+
+/// Downloads the logo of every entry that has one, `options.in_flight` at a time, and hands
+/// each check to `on_check` as it finishes
+pub fn check_logos<F>(
+    entries: Vec<TokenListEntry>,
+    options: &LogoOptions,
+    observer: &dyn Observer,
+    mut on_check: F,
+) -> Result<LogoCounts>
+where
+    F: FnMut(LogoCheck) -> Result<()>,
+{
+    let entries: Vec<TokenListEntry> = entries
+        .into_iter()
+        .filter(|entry| !entry.logo_uri.trim().is_empty())
+        .collect();
+    status!(observer, "Checking {} logos...", entries.len());
+    if let Some(dir) = &options.thumbnail_dir {
+        fs::create_dir_all(dir).map_err(MetabobError::io(dir))?;
+    }
+    let client = Client::builder()
+        .timeout(options.timeout)
+        .build()
+        .map_err(|e| MetabobError::config_with_source("Couldn't build the HTTP client", e))?;
+
+    let mut counts = LogoCounts::default();
+    let progress = observer.progress("logos", entries.len() as u64);
+    let fetch = options.clone();
+    for_each_blocking_in_flight(
+        entries,
+        options.in_flight,
+        move |entry: TokenListEntry| check_logo(&client, &entry, &fetch),
+        |check| {
+            progress.inc(1);
+            counts.checked += 1;
+            *counts.statuses.entry(check.status).or_default() += 1;
+            if check.status != LogoStatus::Ok {
+                warn!(
+                    "{} ({}, chain {}) logo {}: {}",
+                    check.symbol,
+                    check.address,
+                    check.chain_id,
+                    check.status.as_str(),
+                    check.error.as_deref().unwrap_or_default()
+                );
+            }
+            on_check(check)
+        },
+    )?;
+    progress.finish();
+
+    status!(
+        observer,
+        "Checked {} logos, {} flagged.",
+        counts.checked,
+        counts.flagged()
+    );
+    for (logo_status, count) in &counts.statuses {
+        status!(observer, "  {}: {}", logo_status.as_str(), count);
+    }
+    observer.event(
+        "check_logos",
+        json!({ "checked": counts.checked, "statuses": counts.statuses }),
+    );
+    Ok(counts)
+}
+
+fn check_logo(client: &Client, entry: &TokenListEntry, options: &LogoOptions) -> LogoCheck {
+    let check = LogoCheck::new(entry);
+    let address = match parse_pubkey(&entry.address) {
+        Ok(address) => address,
+        Err(e) => return check.flag(LogoStatus::InvalidAddress, e.report()),
+    };
+    let response = match client.get(entry.logo_uri.trim()).send() {
+        Ok(response) => response,
+        Err(e) => return check.flag(LogoStatus::Unreachable, e.to_string()),
+    };
+    if !response.status().is_success() {
+        let status = response.status();
+        return check.flag(LogoStatus::Unreachable, format!("HTTP {}", status));
+    }
+    let mut check = LogoCheck {
+        content_type: response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string()),
+        ..check
+    };
+    if let Some(length) = response.content_length() {
+        if length > options.max_bytes {
+            check.bytes = Some(length);
+            return check.flag(
+                LogoStatus::Oversized,
+                format!(
+                    "{} bytes, over the {} byte limit",
+                    length, options.max_bytes
+                ),
+            );
+        }
+    }
+
+    // Read one byte past the limit so we can tell when the server lied about the length
+    let mut body = Vec::new();
+    if let Err(e) = response.take(options.max_bytes + 1).read_to_end(&mut body) {
+        return check.flag(LogoStatus::Unreachable, e.to_string());
+    }
+    if body.len() as u64 > options.max_bytes {
+        return check.flag(
+            LogoStatus::Oversized,
+            format!("over the {} byte limit", options.max_bytes),
+        );
+    }
+    check.bytes = Some(body.len() as u64);
+    check.sha256 = Some(format!("{:x}", Sha256::digest(&body)));
+
+    if is_svg(&body) {
+        check.format = Some(String::from("svg"));
+        return check;
+    }
+    let format = match image::guess_format(&body) {
+        Ok(format) => format,
+        Err(_) => return check.flag(LogoStatus::NotAnImage, "not a format we recognize"),
+    };
+    check.format = format.extensions_str().first().map(|ext| ext.to_string());
+    let image = match image::load_from_memory_with_format(&body, format) {
+        Ok(image) => image,
+        Err(e) => return check.flag(LogoStatus::NotAnImage, e.to_string()),
+    };
+    check.width = Some(image.width());
+    check.height = Some(image.height());
+
+    if let Some(dir) = &options.thumbnail_dir {
+        let path = thumbnail_path(dir, &address, entry.chain_id);
+        let size = options.thumbnail_size;
+        match image
+            .resize(size, size, FilterType::Triangle)
+            .save_with_format(&path, ImageFormat::Png)
+        {
+            Ok(()) => check.thumbnail = Some(path),
+            Err(e) => warn!("Couldn't save a thumbnail to {}: {}", path.display(), e),
+        }
+    }
+    check
+}
+
+/// `<dir>/<chain id>-<address>.png`, so the same mint listed for more than one chain gets a
+/// thumbnail for each
+pub fn thumbnail_path(dir: &Path, address: &Pubkey, chain_id: u8) -> PathBuf {
+    dir.join(format!("{}-{}.png", chain_id, address))
+}
+
+/// SVGs are XML, which the image decoders don't read, so they only get sniffed
+fn is_svg(body: &[u8]) -> bool {
+    let start = &body[..body.len().min(1024)];
+    let start = String::from_utf8_lossy(start);
+    let start = start.trim_start_matches('\u{feff}').trim_start();
+    (start.starts_with("<svg") || start.starts_with("<?xml")) && start.contains("<svg")
+}
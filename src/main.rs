@@ -1,57 +1,106 @@
-#[macro_use]
-extern crate log;
-
-use anyhow::Result;
+use metabob::config::Settings;
+use metabob::constants::{RPC_DELAY_NS, USE_RATE_LIMIT};
+use metabob::errors::Result;
+use metabob::limiter::configure_rate_limit;
 use metabob::opt::*;
+use metabob::output::{emit, init_logger, set_output_mode, CliObserver};
 use metabob::parse::*;
 use metabob::process_subcommands::*;
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::commitment_config::CommitmentConfig;
-use std::str::FromStr;
-use std::time::Duration;
+use metabob::rpc::RpcPool;
+use metabob::status;
+use serde_json::json;
+use std::{path::Path, process};
 use structopt::StructOpt;
 
-fn main() -> Result<()> {
+fn main() {
     let options = Opt::from_args();
-    let sol_config = parse_solana_config();
+    set_output_mode(options.json, options.quiet > 0);
+    let result = init_logger(
+        options.verbose,
+        options.quiet,
+        options.log_file.as_deref().map(Path::new),
+    )
+    .and_then(|_| run(options));
 
-    let (rpc, commitment) = if let Some(cli_rpc) = options.rpc {
-        (cli_rpc, String::from("confirmed"))
-    } else if let Some(config) = sol_config {
-        (config.json_rpc_url, config.commitment)
-    } else {
-        info!(
-        "Could not find a valid Solana-CLI config file. Defaulting to https://psytrbhymqlkfrhudd.dev.genesysgo.net:8899/ devnet node."
-    );
-        (
-            String::from("https://psytrbhymqlkfrhudd.dev.genesysgo.net:8899/"),
-            String::from("confirmed"),
-        )
-    };
+    if let Err(e) = result {
+        let message = e.report();
+        emit(
+            "error",
+            json!({ "message": message, "code": e.exit_code() }),
+        );
+        eprintln!("Error: {}", message);
+        process::exit(e.exit_code());
+    }
+}
 
-    let heavy_rpc = if let Some(heavy) = options.heavy_rpc {
-        heavy
-    } else {
-        rpc.clone()
-    };
+fn run(options: Opt) -> Result<()> {
+    let sol_config = parse_solana_config(options.config.as_deref())?;
+    let settings = Settings::resolve(&options, sol_config)?;
+    settings.create_output_dir()?;
 
-    let commitment = CommitmentConfig::from_str(&commitment)?;
-    let timeout = Duration::from_secs(options.timeout);
+    match options.command {
+        Command::Db { db_subcommands } => process_db(&settings, db_subcommands)?,
+        Command::Pda(options) => process_pda(&options)?,
+        Command::SPL {
+            spl_subcommands:
+                spl_subcommands @ (SplSubcommands::Diff { .. }
+                | SplSubcommands::Merge { .. }
+                | SplSubcommands::CheckLogos { .. }),
+        } => process_token_lists(&settings, spl_subcommands)?,
+        command => run_rpc_command(&settings, command)?,
+    }
+    status!(CliObserver, "FINISHED!");
+    emit("finished", ());
+    Ok(())
+}
 
-    let client = RpcClient::new_with_timeout_and_commitment(rpc.clone(), timeout, commitment);
-    let heavy_client = RpcClient::new_with_timeout_and_commitment(heavy_rpc, timeout, commitment);
+fn run_rpc_command(settings: &Settings, command: Command) -> Result<()> {
+    configure_rate_limit(&settings.rpc, settings.rps);
+    let client = RpcPool::build(
+        settings.rpc.clone(),
+        settings.heavy_rpc.clone(),
+        &settings.endpoints,
+        settings.timeout,
+        settings.commitment,
+        settings.in_flight,
+    )?;
 
-    println!("RPC: {}", &rpc);
-    println!("Timeout: {}", options.timeout);
-    match options.command {
-        Command::SPL { spl_subcommands } => process_spl(&client, &heavy_client, spl_subcommands)?,
+    if let Some(profile) = &settings.profile {
+        status!(CliObserver, "Profile: {}", profile);
+    }
+    status!(CliObserver, "RPC: {}", &settings.rpc);
+    for endpoint in &client.endpoints()[1..] {
+        status!(
+            CliObserver,
+            "Extra RPC: {} (weight {}{})",
+            endpoint.rpc.url(),
+            endpoint.weight,
+            if endpoint.heavy { ", heavy" } else { "" }
+        );
+    }
+    status!(CliObserver, "Timeout: {}", settings.timeout.as_secs());
+    status!(
+        CliObserver,
+        "Max in-flight requests: {}",
+        client.in_flight()
+    );
+    if *USE_RATE_LIMIT.read().unwrap() {
+        status!(
+            CliObserver,
+            "Rate limit: {} requests per second",
+            1_000_000_000 / *RPC_DELAY_NS.read().unwrap()
+        );
+    }
+    match command {
+        Command::SPL { spl_subcommands } => process_spl(&client, settings, spl_subcommands),
         Command::Metadata {
             metadata_subcommands,
-        } => process_metadata(&client, metadata_subcommands)?,
+        } => process_metadata(&client, settings, metadata_subcommands),
         Command::Gumdrop {
             gumdrop_subcommands,
-        } => process_gumdrop(gumdrop_subcommands)?,
-    };
-    println!("FINISHED!");
-    Ok(())
+        } => process_gumdrop(&client, settings, gumdrop_subcommands),
+        Command::Db { db_subcommands } => process_db(settings, db_subcommands),
+        Command::Pda(options) => process_pda(&options),
+        Command::Inspect { pubkey, commitment } => process_inspect(&client, &pubkey, commitment),
+    }
 }
@@ -0,0 +1,160 @@
+use solana_program::keccak::hashv;
+
+/// Prefix bytes used by the gumdrop program to separate leaf and internal node hashes
+const LEAF_PREFIX: &[u8] = &[0x00];
+const INTERMEDIATE_PREFIX: &[u8] = &[0x01];
+
+/// Merkle tree matching the one used by the gumdrop program and the Metaplex JS CLI.
+/// Pairs are hashed in sorted order so proofs don't need to carry left/right flags,
+/// and an odd node at the end of a layer is carried up unchanged.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    layers: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    pub fn new(leaves: &[Vec<u8>]) -> MerkleTree {
+        let leaf_layer: Vec<[u8; 32]> = leaves.iter().map(|leaf| leaf_hash(leaf)).collect();
+        let mut layers = vec![leaf_layer];
+
+        while layers.last().unwrap().len() > 1 {
+            let next = layers
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [first, second] => internal_hash(first, second),
+                    [only] => *only,
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(next);
+        }
+
+        MerkleTree { layers }
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.layers
+            .last()
+            .and_then(|layer| layer.first().copied())
+            .unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers[0].is_empty()
+    }
+
+    pub fn proof(&self, index: usize) -> Vec<[u8; 32]> {
+        let mut index = index;
+        let mut proof = vec![];
+        for layer in &self.layers {
+            let pair_index = index ^ 1;
+            if pair_index < layer.len() {
+                proof.push(layer[pair_index]);
+            }
+            index /= 2;
+        }
+        proof
+    }
+}
+
+pub fn leaf_hash(leaf: &[u8]) -> [u8; 32] {
+    hashv(&[LEAF_PREFIX, leaf]).0
+}
+
+pub fn internal_hash(first: &[u8; 32], second: &[u8; 32]) -> [u8; 32] {
+    let (first, second) = if first <= second {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    hashv(&[INTERMEDIATE_PREFIX, first, second]).0
+}
+
+/// Same check the gumdrop program does on claim
+pub fn verify_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: &[u8]) -> bool {
+    let computed = proof.iter().fold(leaf_hash(leaf), |node, sibling| {
+        internal_hash(&node, sibling)
+    });
+    computed == *root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: usize) -> Vec<Vec<u8>> {
+        [b"a", b"b", b"c"][..n]
+            .iter()
+            .map(|leaf| leaf.to_vec())
+            .collect()
+    }
+
+    fn hex(bytes: &[u8; 32]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn root_of_one_leaf_is_its_hash() {
+        let tree = MerkleTree::new(&leaves(1));
+        assert_eq!(
+            hex(&tree.root()),
+            "9722201502e620d70d78ee63045f3493812c206b988cbbe76c28918a7364fdbd"
+        );
+        assert!(tree.proof(0).is_empty());
+    }
+
+    #[test]
+    fn root_of_two_leaves() {
+        let tree = MerkleTree::new(&leaves(2));
+        assert_eq!(
+            hex(&tree.root()),
+            "00d25e3ecfd5a8430c58b5562d4a00f53ce3e76001e3683df8496c541fecb9da"
+        );
+    }
+
+    #[test]
+    fn root_of_odd_leaves_carries_the_last_one_up() {
+        let tree = MerkleTree::new(&leaves(3));
+        assert_eq!(
+            hex(&tree.root()),
+            "3f6c2d6d0c2fcd67795ea50af0dc85c8e2df8832efe3c49e36d8fe2e71bcc07b"
+        );
+        // The last leaf has no pair in the bottom layer, so its proof skips it
+        assert_eq!(tree.proof(2).len(), 1);
+    }
+
+    #[test]
+    fn valid_proofs_pass() {
+        let leaves = leaves(3);
+        let tree = MerkleTree::new(&leaves);
+        for (i, leaf) in leaves.iter().enumerate() {
+            assert!(verify_proof(&tree.proof(i), &tree.root(), leaf));
+        }
+    }
+
+    #[test]
+    fn tampered_leaf_fails() {
+        let tree = MerkleTree::new(&leaves(3));
+        assert!(!verify_proof(&tree.proof(0), &tree.root(), b"x"));
+        // Each proof only works for its own leaf
+        assert!(!verify_proof(&tree.proof(0), &tree.root(), b"c"));
+    }
+
+    #[test]
+    fn tampered_proof_fails() {
+        let leaves = leaves(3);
+        let tree = MerkleTree::new(&leaves);
+        let mut proof = tree.proof(1);
+        proof[0][0] ^= 1;
+        assert!(!verify_proof(&proof, &tree.root(), &leaves[1]));
+        let mut short = tree.proof(1);
+        short.pop();
+        assert!(!verify_proof(&short, &tree.root(), &leaves[1]));
+    }
+}
@@ -1,170 +1,220 @@
-use crate::limiter::create_rate_limiter;
-use crate::{
-    constants::{MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH, MAX_URI_LENGTH, USE_RATE_LIMIT},
-    parse::parse_solana_config,
-};
-use anyhow::Result;
-use indicatif::ParallelProgressIterator;
-use log::{error, info};
-use mpl_token_metadata::{
-    instruction::sign_metadata, state::Metadata, ID as TOKEN_METADATA_PROGRAM_ID,
-};
+use crate::constants::{MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH, MAX_URI_LENGTH};
+use crate::decode::decode_metadata_account;
+use crate::errors::{MetabobError, Result};
+use crate::observer::Observer;
+use crate::rpc::RpcPool;
+use crate::runtime::for_each_in_flight;
+use crate::status;
+use log::{debug, error, info};
+use mpl_token_metadata::{instruction::sign_metadata, ID as TOKEN_METADATA_PROGRAM_ID};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-use retry::{delay::Exponential, retry};
+use serde_json::json;
 use solana_account_decoder::UiAccountEncoding;
 use solana_client::{
-    rpc_client::RpcClient,
     rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
     rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
 };
-use solana_program::account_info::AccountInfo;
 use solana_sdk::{
     account::Account,
-    commitment_config::{CommitmentConfig, CommitmentLevel},
+    commitment_config::CommitmentConfig,
+    compute_budget,
+    instruction::Instruction,
     pubkey::Pubkey,
-    signature::{read_keypair_file, Signature},
+    signature::Signature,
     signer::{keypair::Keypair, Signer},
     transaction::Transaction,
 };
-use std::str::FromStr;
-use std::{
-    fs::OpenOptions,
-    sync::{Arc, Mutex},
-};
+use std::sync::{Arc, Mutex};
 
-pub fn count_creators(client: &RpcClient, creator: String) -> Result<Vec<Pubkey>> {
-    let creator_pubkey =
-        Pubkey::from_str(&creator).expect("Couldn't parse a pubkey from your option");
-    let creator_string = creator_pubkey.to_string();
-    let accounts_vec: Arc<Mutex<Vec<Pubkey>>> = Arc::new(Mutex::new(Vec::new()));
+/// What came of a sign_all run
+#[derive(Debug, Clone, Default)]
+pub struct SignReport {
+    /// Metadata accounts that still needed the creator's signature
+    pub unverified: Vec<Pubkey>,
+    pub signed: Vec<(Pubkey, Signature)>,
+    pub failed: Vec<(Pubkey, String)>,
+}
+
+pub fn count_creators(
+    client: &RpcPool,
+    creator: &Pubkey,
+    commitment: CommitmentConfig,
+    observer: &dyn Observer,
+) -> Result<Vec<Pubkey>> {
+    let creator_string = creator.to_string();
+    let mut accounts_vec: Vec<Pubkey> = Vec::new();
 
     let index_vec: Vec<usize> = vec![0, 1, 2, 3, 4];
-    println!(
+    status!(
+        observer,
         "Looking for metadata accounts that the following address can sign: {}",
         creator_string
     );
-    index_vec.par_iter().for_each(|i| {
-        let accounts_vec = accounts_vec.clone();
-        let next_accounts = get_metadata_creator_accounts(client, &creator_string, *i)
-            .unwrap_or_else(|_| panic!("Couldn't finish the GPA for creator index {}", i));
-        let total = next_accounts.len();
-        let unsigned_mints: Arc<Mutex<Vec<Pubkey>>> = Arc::new(Mutex::new(Vec::new()));
-        next_accounts.par_iter().for_each(|(pubkey, account)| {
-            let mut account = account.clone();
-            let account_info = AccountInfo::from((pubkey, &mut account));
-            let metadata_account =
-                Metadata::from_account_info(&account_info).expect("Couldn't deserialize metadata");
-            let creator = &metadata_account.data.creators.unwrap()[*i];
-            if !creator.verified {
-                unsigned_mints.lock().unwrap().push(*pubkey);
+    for_each_in_flight(
+        index_vec,
+        client.in_flight(),
+        |i: usize| {
+            let creator = &creator_string;
+            async move {
+                let accounts = get_metadata_creator_accounts(client, creator, i, commitment).await;
+                (i, accounts)
             }
-        });
-        let mut unsigned_mints = Arc::try_unwrap(unsigned_mints)
-            .unwrap()
-            .into_inner()
-            .unwrap();
-        println!(
-            "In position {}:\n  Found {} unverified of {} total",
-            i,
-            unsigned_mints.len(),
-            total
-        );
-        accounts_vec.lock().unwrap().append(&mut unsigned_mints);
-    });
-
-    let accounts_vec = Arc::try_unwrap(accounts_vec).unwrap().into_inner().unwrap();
+        },
+        |(i, next_accounts)| {
+            let next_accounts = next_accounts?;
+            let total = next_accounts.len();
+            let unsigned_mints: Arc<Mutex<Vec<Pubkey>>> = Arc::new(Mutex::new(Vec::new()));
+            next_accounts.par_iter().for_each(|(pubkey, account)| {
+                let metadata_account = match decode_metadata_account(account) {
+                    Ok(metadata_account) => metadata_account,
+                    Err(e) => {
+                        debug!("Skipping metadata {}: {}", pubkey, e);
+                        return;
+                    }
+                };
+                let creator = metadata_account
+                    .creators
+                    .as_ref()
+                    .and_then(|creators| creators.get(i));
+                let unverified = matches!(creator, Some(creator) if !creator.verified);
+                if unverified {
+                    unsigned_mints.lock().unwrap().push(*pubkey);
+                }
+            });
+            let mut unsigned_mints = Arc::try_unwrap(unsigned_mints)
+                .unwrap()
+                .into_inner()
+                .unwrap();
+            status!(
+                observer,
+                "In position {}:\n  Found {} unverified of {} total",
+                i,
+                unsigned_mints.len(),
+                total
+            );
+            observer.event(
+                "creator_position",
+                json!({ "position": i, "unverified": unsigned_mints.len(), "total": total }),
+            );
+            accounts_vec.append(&mut unsigned_mints);
+            Ok(())
+        },
+    )?;
     let accounts_strings_vec: Vec<String> =
         accounts_vec.iter().map(|key| key.to_string()).collect();
 
-    println!(
+    status!(
+        observer,
         "Found {} total metadata accounts that still need to be signed",
         accounts_vec.len()
     );
+    observer.event("unverified", json!({ "metadata": accounts_strings_vec }));
 
-    if !accounts_vec.is_empty() {
-        let file1 = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open("./metadata_list.json");
-        match file1 {
-            Err(_) => println!("Error opening metadata_keys file"),
-            Ok(mut f) => {
-                println!("Saving metadata list info file...");
-                serde_json::to_writer(&mut f, &accounts_strings_vec)?;
-            }
-        }
-
-        let file2 = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open("./metadata_pubkeys.json");
-        match file2 {
-            Err(_) => println!("Error opening metadata pubkeys file"),
-            Ok(mut f) => {
-                println!("Saving metadata pubkeys info file...");
-                serde_json::to_writer(&mut f, &accounts_vec)?;
-            }
-        }
-    }
     Ok(accounts_vec)
 }
 
-pub fn sign_all(client: &RpcClient, keypair_path: Option<String>) -> Result<()> {
-    let solana_opts = parse_solana_config();
-    let keypair: Keypair = match keypair_path {
-        Some(path) => read_keypair_file(path).expect("Uh I cant read that keypair file :cry:"),
-        None => {
-            let solana_config = solana_opts
-                .expect("You didn't pass in a keypair and your Solana config is no bueno");
-            let keypair_path = solana_config.keypair_path;
-            read_keypair_file(keypair_path)
-                .expect("Uh I cant read that keypair file from your Solana config :cry:")
-        }
-    };
+pub fn sign_all(
+    client: &RpcPool,
+    keypair: Arc<Keypair>,
+    commitment: CommitmentConfig,
+    priority_fee: Option<u64>,
+    observer: &dyn Observer,
+) -> Result<SignReport> {
+    let accounts_vec = count_creators(client, &keypair.pubkey(), commitment, observer)?;
+    sign_accounts(
+        client,
+        keypair,
+        accounts_vec,
+        commitment,
+        priority_fee,
+        observer,
+        |_, _| Ok(()),
+    )
+}
 
-    let creator_pubkey = keypair.pubkey();
-    let creator_string = creator_pubkey.to_string();
-    let accounts_vec = count_creators(client, creator_string)?;
+/// Signs each of `accounts_vec` as the creator in `keypair`. Failures are collected in the
+/// report rather than stopping the run. Each result is also handed to `on_result` as soon as
+/// it's in, so it can be saved before the rest of the run finishes
+pub fn sign_accounts<F>(
+    client: &RpcPool,
+    keypair: Arc<Keypair>,
+    accounts_vec: Vec<Pubkey>,
+    commitment: CommitmentConfig,
+    priority_fee: Option<u64>,
+    observer: &dyn Observer,
+    mut on_result: F,
+) -> Result<SignReport>
+where
+    F: FnMut(&Pubkey, std::result::Result<&Signature, &str>) -> Result<()>,
+{
+    let mut signed: Vec<(Pubkey, Signature)> = Vec::new();
+    let mut failed: Vec<(Pubkey, String)> = Vec::new();
 
     if !accounts_vec.is_empty() {
-        println!("Now signing metadata...");
-
-        let use_rate_limit = *USE_RATE_LIMIT.read().unwrap();
-        let handle = create_rate_limiter();
+        status!(observer, "Now signing metadata...");
 
         // also basically taken directly from metaboss
-        accounts_vec
-            .par_iter()
-            .progress()
-            .for_each(|metadata_pubkey| {
-                let mut handle = handle.clone();
-                if use_rate_limit {
-                    handle.wait();
-                }
+        let progress = observer.progress("sign", accounts_vec.len() as u64);
+        let keypair = &keypair;
+        for_each_in_flight(
+            accounts_vec.clone(),
+            client.in_flight(),
+            |metadata_pubkey: Pubkey| async move {
+                let result = sign(client, keypair, metadata_pubkey, commitment, priority_fee).await;
+                (metadata_pubkey, result)
+            },
+            |(metadata_pubkey, result)| {
+                progress.inc(1);
                 // Try to sign all accounts, print any errors that crop up.
-                match sign(client, &keypair, *metadata_pubkey) {
-                    Ok(sig) => info!("{}", sig),
-                    Err(e) => error!("{}", e),
+                match result {
+                    Ok(sig) => {
+                        info!("Signed {}: {}", metadata_pubkey, sig);
+                        observer.event(
+                            "signed",
+                            json!({ "metadata": metadata_pubkey.to_string(), "signature": sig.to_string() }),
+                        );
+                        on_result(&metadata_pubkey, Ok(&sig))?;
+                        signed.push((metadata_pubkey, sig));
+                    }
+                    Err(e) => {
+                        let report = e.report();
+                        error!("Couldn't sign {}: {}", metadata_pubkey, report);
+                        observer.event(
+                            "sign_failed",
+                            json!({ "metadata": metadata_pubkey.to_string(), "error": report }),
+                        );
+                        on_result(&metadata_pubkey, Err(&report))?;
+                        failed.push((metadata_pubkey, report));
+                    }
                 }
-            });
+                Ok(())
+            },
+        )?;
+        progress.finish();
     }
-    Ok(())
+    Ok(SignReport {
+        unverified: accounts_vec,
+        signed,
+        failed,
+    })
 }
 
 // From metaboss
-pub fn get_metadata_creator_accounts(
-    client: &RpcClient,
+pub async fn get_metadata_creator_accounts(
+    client: &RpcPool,
     creator: &str,
     position: usize,
+    commitment: CommitmentConfig,
 ) -> Result<Vec<(Pubkey, Account)>> {
     if position > 4 {
-        error!("CM Creator position cannot be greator than 4");
-        std::process::exit(1);
+        return Err(MetabobError::Validation(String::from(
+            "CM Creator position cannot be greator than 4",
+        )));
     }
 
     let config = RpcProgramAccountsConfig {
-        filters: Some(vec![RpcFilterType::Memcmp(Memcmp {
-            offset: 1 + // key
+        filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new(
+            1 + // key
             32 + // update auth
             32 + // mint
             4 + // name string length
@@ -182,41 +232,64 @@ pub fn get_metadata_creator_accounts(
                 1 + // verified
                 1 // share
             ),
-            bytes: MemcmpEncodedBytes::Base58(creator.to_string()),
-            encoding: None,
-        })]),
+            MemcmpEncodedBytes::Base58(creator.to_string()),
+        ))]),
         account_config: RpcAccountInfoConfig {
             encoding: Some(UiAccountEncoding::Base64),
             data_slice: None,
-            commitment: Some(CommitmentConfig {
-                commitment: CommitmentLevel::Confirmed,
-            }),
+            commitment: Some(commitment),
+            min_context_slot: None,
         },
         with_context: None,
     };
 
-    let accounts = client.get_program_accounts_with_config(&TOKEN_METADATA_PROGRAM_ID, config)?;
+    let accounts = client
+        .get_program_accounts_with_config_async(&TOKEN_METADATA_PROGRAM_ID, config)
+        .await?;
 
     Ok(accounts)
 }
 
 // From metaboss
-pub fn sign(client: &RpcClient, creator: &Keypair, metadata_pubkey: Pubkey) -> Result<Signature> {
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let ix = sign_metadata(TOKEN_METADATA_PROGRAM_ID, metadata_pubkey, creator.pubkey());
+pub async fn sign(
+    client: &RpcPool,
+    creator: &Keypair,
+    metadata_pubkey: Pubkey,
+    commitment: CommitmentConfig,
+    priority_fee: Option<u64>,
+) -> Result<Signature> {
+    // The blockhash, send and confirmation all go to one endpoint, so none of them trip over
+    // an endpoint that's behind the others
+    let rpc = client.pin();
+    let (recent_blockhash, last_valid_block_height) =
+        rpc.get_latest_blockhash_and_height(commitment).await?;
+    let mut ixs = vec![];
+    if let Some(micro_lamports) = priority_fee {
+        ixs.push(set_compute_unit_price(micro_lamports));
+    }
+    ixs.push(sign_metadata(
+        TOKEN_METADATA_PROGRAM_ID,
+        metadata_pubkey,
+        creator.pubkey(),
+    ));
     let tx = Transaction::new_signed_with_payer(
-        &[ix],
+        &ixs,
         Some(&creator.pubkey()),
         &[creator],
         recent_blockhash,
     );
 
     // Send tx with retries.
-    let res = retry(
-        Exponential::from_millis_with_factor(250, 2.0).take(3),
-        || client.send_and_confirm_transaction(&tx),
-    );
-    let sig = res?;
+    let sig = rpc
+        .send_and_confirm_until(&tx, last_valid_block_height, commitment)
+        .await?;
 
     Ok(sig)
 }
+
+/// ComputeBudget SetComputeUnitPrice, which the solana-sdk we're on doesn't have a builder for
+pub fn set_compute_unit_price(micro_lamports: u64) -> Instruction {
+    let mut data = vec![3];
+    data.extend_from_slice(&micro_lamports.to_le_bytes());
+    Instruction::new_with_bytes(compute_budget::id(), &data, vec![])
+}
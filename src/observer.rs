@@ -0,0 +1,43 @@
+use serde_json::Value;
+
+/// Prints a progress message through an observer, e.g. `status!(observer, "Found {}", n)`
+#[macro_export]
+macro_rules! status {
+    ($observer:expr, $($arg:tt)*) => {{
+        #[allow(unused_imports)]
+        use $crate::observer::Observer as _;
+        $observer.status(&format!($($arg)*))
+    }};
+}
+
+/// Hooks for following long running calls. Everything defaults to doing nothing, so library
+/// users only implement what they care about. The CLI turns these into status lines,
+/// progress bars and --json events
+pub trait Observer: Sync {
+    /// Human readable progress message
+    fn status(&self, _message: &str) {}
+
+    /// Structured milestone or result, e.g. `mints` with the number of mints found
+    fn event(&self, _name: &str, _data: Value) {}
+
+    /// Start of a task over `total` items. The returned handle is ticked once per item
+    fn progress(&self, _task: &'static str, _total: u64) -> Box<dyn Progress> {
+        Box::new(Silent)
+    }
+}
+
+pub trait Progress: Send + Sync {
+    fn inc(&self, delta: u64);
+
+    fn finish(&self) {}
+}
+
+/// Observer that ignores everything
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Silent;
+
+impl Observer for Silent {}
+
+impl Progress for Silent {
+    fn inc(&self, _delta: u64) {}
+}
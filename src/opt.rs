@@ -1,3 +1,9 @@
+use crate::gumdrop::{ClaimType, HandleType};
+use crate::parse::parse_rps;
+use crate::rpc::EndpointConfig;
+use crate::token_list::FieldPreference;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::num::NonZeroU32;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -7,16 +13,77 @@ use structopt::StructOpt;
 )]
 pub struct Opt {
     // RPC endpoint url
-    #[structopt(short, long)]
+    #[structopt(short, long, env = "METABOB_RPC")]
     pub rpc: Option<String>,
 
     // Heavy RPC endpoint url
-    #[structopt(long)]
+    #[structopt(long, env = "METABOB_HEAVY_RPC")]
     pub heavy_rpc: Option<String>,
 
-    /// Timeout to override default value of 60 seconds
-    #[structopt(short, long, default_value = "60")]
-    pub timeout: u64,
+    /// Extra RPC endpoint to spread requests over, as URL[,weight=N][,rps=N][,heavy]. Can be repeated
+    #[structopt(short, long = "endpoint", number_of_values = 1)]
+    pub endpoints: Vec<EndpointConfig>,
+
+    /// Timeout in seconds. Defaults to 60
+    #[structopt(short, long, env = "METABOB_TIMEOUT")]
+    pub timeout: Option<u64>,
+
+    /// Max RPC requests per second. Defaults to the known limit for the RPC host, if any
+    #[structopt(long, env = "METABOB_RPS", parse(try_from_str = parse_rps))]
+    pub rps: Option<NonZeroU32>,
+
+    /// Max RPC requests waiting on a response at once in big scans and signing. Defaults to 64
+    #[structopt(long, env = "METABOB_IN_FLIGHT")]
+    pub in_flight: Option<usize>,
+
+    /// Commitment level: processed, confirmed or finalized
+    #[structopt(long, env = "METABOB_COMMITMENT")]
+    pub commitment: Option<String>,
+
+    /// Path to the keypair used for signing
+    #[structopt(long, env = "METABOB_KEYPAIR")]
+    pub keypair: Option<String>,
+
+    /// Directory to save files to and read them back from. Defaults to the current directory
+    #[structopt(long, env = "METABOB_OUTPUT_DIR")]
+    pub output_dir: Option<String>,
+
+    /// SQLite database the spl and metadata commands also keep their results in, and read
+    /// their inputs from instead of the output dir files
+    #[structopt(long, env = "METABOB_DB")]
+    pub db: Option<String>,
+
+    /// Priority fee in micro-lamports per compute unit for sent transactions
+    #[structopt(long, env = "METABOB_PRIORITY_FEE")]
+    pub priority_fee: Option<u64>,
+
+    /// Profile to use from the metabob config file
+    #[structopt(long, env = "METABOB_PROFILE")]
+    pub profile: Option<String>,
+
+    /// More logging. Repeat for even more (-vv)
+    #[structopt(short, long, parse(from_occurrences))]
+    pub verbose: u8,
+
+    /// Less output. Hides progress and, repeated, warnings (-qq)
+    #[structopt(short, long, parse(from_occurrences))]
+    pub quiet: u8,
+
+    /// Append logs to this file instead of stderr
+    #[structopt(long, env = "METABOB_LOG_FILE")]
+    pub log_file: Option<String>,
+
+    /// Emit progress and results as newline-delimited JSON events on stdout
+    #[structopt(long)]
+    pub json: bool,
+
+    /// Path to the Solana CLI config file. Defaults to ~/.config/solana/cli/config.yml
+    #[structopt(long, env = "SOLANA_CONFIG")]
+    pub config: Option<String>,
+
+    /// Path to the metabob config file. Defaults to ~/.config/metabob/config.yml
+    #[structopt(long, env = "METABOB_CONFIG")]
+    pub metabob_config: Option<String>,
 
     #[structopt(subcommand)]
     pub command: Command,
@@ -44,6 +111,30 @@ pub enum Command {
         #[structopt(subcommand)]
         gumdrop_subcommands: GumdropSubcommands,
     },
+
+    /// Fetch any account, work out what it is and print it decoded as JSON. For a mint, its
+    /// metadata and edition accounts are fetched too
+    #[structopt(name = "inspect")]
+    Inspect {
+        /// Address of the account
+        pubkey: String,
+
+        /// Commitment for this command, overriding the global --commitment
+        #[structopt(long)]
+        commitment: Option<CommitmentConfig>,
+    },
+
+    /// Derive the token metadata, token account, candy machine and gumdrop addresses the seeds
+    /// given are enough for
+    #[structopt(name = "pda")]
+    Pda(PdaOptions),
+
+    /// Stuff with the --db database
+    #[structopt(name = "db")]
+    Db {
+        #[structopt(subcommand)]
+        db_subcommands: DbSubcommands,
+    },
 }
 
 #[derive(Debug, StructOpt)]
@@ -54,6 +145,79 @@ pub enum SplSubcommands {
         /// Don't save intermediate files
         #[structopt(short, long)]
         no_save: bool,
+
+        /// Commitment for this command, overriding the global --commitment
+        #[structopt(long)]
+        commitment: Option<CommitmentConfig>,
+    },
+
+    /// Bring the last do_everything or refresh up to date, only fetching metadata that could
+    /// have changed, and save what changed in the token list
+    #[structopt(name = "refresh")]
+    Refresh {
+        /// Commitment for this command, overriding the global --commitment
+        #[structopt(long)]
+        commitment: Option<CommitmentConfig>,
+    },
+
+    /// Compare two token lists by address and show every added, removed and changed entry
+    #[structopt(name = "diff")]
+    Diff {
+        /// Older token list: a json array, a {"tokens": [...]} token list, or .ndjson
+        old: String,
+
+        /// Newer token list, in any of the same formats
+        new: String,
+
+        /// Also save the diff as json to this file
+        #[structopt(short, long)]
+        out: Option<String>,
+    },
+
+    /// Merge a token list generated from chain data into a curated one, sorted by address
+    #[structopt(name = "merge")]
+    Merge {
+        /// Curated token list
+        #[structopt(long)]
+        curated: String,
+
+        /// Generated token list. Defaults to draft/tokenlist.ndjson in the output dir
+        #[structopt(long)]
+        generated: Option<String>,
+
+        /// Which list wins a field both have, as FIELD=curated|chain. Can be repeated.
+        /// By default the curated list wins everything but decimals
+        #[structopt(long = "prefer", number_of_values = 1)]
+        prefer: Vec<FieldPreference>,
+
+        /// Where to save the merged list. Defaults to draft/merged_tokenlist.json in the output dir
+        #[structopt(short, long)]
+        out: Option<String>,
+    },
+
+    /// Download every logo in draft/uri_tokenlist.json, check it's an image under the size
+    /// limit, and save what was found to draft/logo_report.json
+    #[structopt(name = "check_logos")]
+    CheckLogos {
+        /// Token list to check. Defaults to draft/uri_tokenlist.json in the output dir
+        #[structopt(long)]
+        token_list: Option<String>,
+
+        /// Flag logos bigger than this many bytes. Defaults to 1 MiB
+        #[structopt(long)]
+        max_size: Option<u64>,
+
+        /// Save a PNG thumbnail of each logo to this directory, named after the token address
+        #[structopt(long)]
+        thumbnails: Option<String>,
+
+        /// Largest thumbnail width and height in pixels. Defaults to 64
+        #[structopt(long)]
+        thumbnail_size: Option<u32>,
+
+        /// Where to save the report. Defaults to draft/logo_report.json in the output dir
+        #[structopt(short, long)]
+        out: Option<String>,
     },
 
     /// Get all fungible SPL token mints
@@ -62,6 +226,10 @@ pub enum SplSubcommands {
         /// Don't save mint accounts to file
         #[structopt(short, long)]
         no_save: bool,
+
+        /// Commitment for this command, overriding the global --commitment
+        #[structopt(long)]
+        commitment: Option<CommitmentConfig>,
     },
 
     /// Get all metadata accounts
@@ -70,6 +238,10 @@ pub enum SplSubcommands {
         /// Don't save metadata accounts to file
         #[structopt(long)]
         no_save: bool,
+
+        /// Commitment for this command, overriding the global --commitment
+        #[structopt(long)]
+        commitment: Option<CommitmentConfig>,
     },
 
     /// Get SPL Token list json
@@ -80,6 +252,19 @@ pub enum SplSubcommands {
         no_save: bool,
     },
 
+    /// Build the token list from the metadata accounts like get_token_list, but leave out
+    /// tokens that look broken or like scams and save why to draft/rejected_tokens.json
+    #[structopt(name = "filter")]
+    Filter {
+        /// YAML or TOML file with the filter rules. Every check is on by default
+        #[structopt(long)]
+        rules: Option<String>,
+
+        /// Don't save the filtered token list to file
+        #[structopt(long)]
+        no_save: bool,
+    },
+
     /// Parse SPL Token list json
     #[structopt(name = "parse_token_list")]
     ParseTokenList {
@@ -101,6 +286,10 @@ pub enum MetadataSubcommands {
         /// Path to creator's keypair file
         #[structopt(short, long)]
         keypair: Option<String>,
+
+        /// Commitment for this command, overriding the global --commitment
+        #[structopt(long)]
+        commitment: Option<CommitmentConfig>,
     },
 
     /// Counts all NFTs that have the provided creator listed in the creator array
@@ -109,6 +298,10 @@ pub enum MetadataSubcommands {
         /// Base58 creator address
         #[structopt(short, long)]
         creator: String,
+
+        /// Commitment for this command, overriding the global --commitment
+        #[structopt(long)]
+        commitment: Option<CommitmentConfig>,
     },
 }
 
@@ -121,16 +314,136 @@ pub enum GumdropSubcommands {
         #[structopt(short, long)]
         number: u64,
 
-        /// Amount of tokens that each wallet gets
+        /// Amount of tokens (or candy machine mints) that each wallet gets. Not used for editions
         #[structopt(short, long)]
-        amount: u64,
+        amount: Option<u64>,
+
+        /// First edition number to hand out for edition claims. Editions are numbered on from it
+        /// across every list made. Defaults to 1
+        #[structopt(long)]
+        start_edition: Option<u64>,
 
         /// Number of times to make a list
         #[structopt(short, long)]
         repeat: Option<u64>,
 
-        /// Path to file of base58 pubkeys to chuck in the gumdrop in array form
+        /// Path to file of handles to chuck in the gumdrop in array form
+        #[structopt(short = "p", long, alias = "pubkey-path")]
+        handle_path: Option<String>,
+
+        /// Kind of handle in the list: pubkey, email, discord or twitter
+        #[structopt(long, default_value = "pubkey")]
+        handle_type: HandleType,
+
+        /// Kind of claim: token, edition or candy
+        #[structopt(short, long, default_value = "token")]
+        claim_type: ClaimType,
+
+        /// Seed for the random wallets and positions. The same seed makes the same files
         #[structopt(short, long)]
-        pubkey_path: Option<String>,
+        seed: Option<u64>,
     },
+
+    /// Builds the gumdrop merkle tree and saves a claim file with a proof for each recipient
+    #[structopt(name = "build_tree")]
+    BuildTree {
+        /// Path to the distribution json file
+        #[structopt(short, long)]
+        distribution: String,
+
+        /// Base58 address being dropped: the token mint, the master edition mint or the candy machine
+        #[structopt(short, long, alias = "mint")]
+        target: String,
+
+        /// Directory to save the root and claim files to. Defaults to claims in the output dir
+        #[structopt(short, long)]
+        out_dir: Option<String>,
+
+        /// Kind of handle in the list: pubkey, email, discord or twitter
+        #[structopt(long, default_value = "pubkey")]
+        handle_type: HandleType,
+
+        /// Kind of claim: token, edition or candy
+        #[structopt(short, long, default_value = "token")]
+        claim_type: ClaimType,
+
+        /// Base58 distributor base pubkey. Needed to derive claim secrets for non-pubkey handles
+        #[structopt(short, long)]
+        base: Option<String>,
+    },
+
+    /// Checks a claim file's proof against a merkle root
+    #[structopt(name = "verify_proof")]
+    VerifyProof {
+        /// Path to the claim file
+        #[structopt(short, long)]
+        claim: String,
+
+        /// Base58 merkle root to check against. Defaults to the root saved in the claim file
+        #[structopt(short, long)]
+        root: Option<String>,
+    },
+
+    /// Checks a distribution file for bad handles and amounts and totals it up
+    #[structopt(name = "validate")]
+    Validate {
+        /// Path to the distribution json file
+        distribution: String,
+
+        /// Kind of handle in the list: pubkey, email, discord or twitter
+        #[structopt(long, default_value = "pubkey")]
+        handle_type: HandleType,
+
+        /// Kind of claim: token, edition or candy
+        #[structopt(short, long, default_value = "token")]
+        claim_type: ClaimType,
+
+        /// Base58 mint of the token being dropped, to check decimals and supply against
+        #[structopt(short, long)]
+        mint: Option<String>,
+
+        /// Maximum total amount (in base units) the drop is allowed to hand out
+        #[structopt(short, long)]
+        budget: Option<u64>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum DbSubcommands {
+    /// Runs a SQL statement against the database and prints any rows it returns
+    #[structopt(name = "query")]
+    Query {
+        /// SQL to run, e.g. "SELECT symbol, name FROM token_list LIMIT 10"
+        sql: String,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub struct PdaOptions {
+    /// Base58 mint, for its metadata and edition accounts and the rest below
+    pub mint: Option<String>,
+
+    /// Print number, for the edition marker of the master edition mint
+    #[structopt(long)]
+    pub edition: Option<u64>,
+
+    /// Base58 collection or use authority, for its authority records
+    #[structopt(long)]
+    pub authority: Option<String>,
+
+    /// Base58 wallet, for its associated token account and the token record of that
+    #[structopt(long)]
+    pub owner: Option<String>,
+
+    /// Base58 token account, for its token record
+    #[structopt(long)]
+    pub token_account: Option<String>,
+
+    /// Base58 v2 candy machine, for its creator
+    #[structopt(long)]
+    pub candy_machine: Option<String>,
+
+    /// Base58 gumdrop distributor base, for the distributor and its wallet
+    #[structopt(long)]
+    pub base: Option<String>,
 }
@@ -0,0 +1,132 @@
+use crate::constants::{JSON_OUTPUT, QUIET};
+use crate::errors::{MetabobError, Result};
+use crate::observer::{Observer, Progress};
+use env_logger::{Builder, Target};
+use indicatif::ProgressBar;
+use log::LevelFilter;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::{env, fs::OpenOptions, path::Path, thread, time::Duration};
+
+/// Info by default, each -v goes one level noisier and each -q one level quieter.
+/// Dependencies stay at warn unless RUST_LOG says otherwise
+pub fn init_logger(verbose: u8, quiet: u8, log_file: Option<&Path>) -> Result<()> {
+    let level = match 3 + verbose as i16 - quiet as i16 {
+        i16::MIN..=0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    let mut builder = Builder::new();
+    builder
+        .filter_level(level.min(LevelFilter::Warn))
+        .filter_module("metabob", level);
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    if let Some(path) = log_file {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(MetabobError::io(path))?;
+        builder.target(Target::Pipe(Box::new(file)));
+    }
+    builder
+        .try_init()
+        .map_err(|e| MetabobError::config_with_source("Couldn't start the logger", e))
+}
+
+pub fn set_output_mode(json: bool, quiet: bool) {
+    *JSON_OUTPUT.write().unwrap() = json;
+    *QUIET.write().unwrap() = quiet;
+}
+
+pub fn is_json() -> bool {
+    *JSON_OUTPUT.read().unwrap()
+}
+
+pub fn status(message: &str) {
+    if is_json() {
+        emit("status", json!({ "message": message }));
+    } else if !*QUIET.read().unwrap() {
+        println!("{}", message);
+    }
+}
+
+/// Emits one NDJSON event line in --json mode, nothing otherwise. Struct fields are
+/// flattened into the event, anything else ends up under `data`
+pub fn emit<T: Serialize>(event: &str, data: T) {
+    if !is_json() {
+        return;
+    }
+    let mut line = Map::new();
+    line.insert(String::from("event"), Value::from(event));
+    match serde_json::to_value(data) {
+        Ok(Value::Object(fields)) => line.extend(fields),
+        Ok(Value::Null) => {}
+        Ok(value) => {
+            line.insert(String::from("data"), value);
+        }
+        Err(e) => {
+            line.insert(String::from("error"), Value::from(e.to_string()));
+        }
+    }
+    println!("{}", Value::Object(line));
+}
+
+/// Progress bar for a task of `len` items. Hidden with -q, and in --json mode it's
+/// replaced by a `progress` event every second until the bar is dropped
+pub fn progress_bar(len: u64, task: &'static str) -> ProgressBar {
+    if is_json() {
+        let bar = ProgressBar::hidden();
+        bar.set_length(len);
+        let weak = bar.downgrade();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(1));
+            match weak.upgrade() {
+                Some(bar) if !bar.is_finished() => emit(
+                    "progress",
+                    json!({ "task": task, "done": bar.position(), "total": bar.length() }),
+                ),
+                _ => break,
+            }
+        });
+        bar
+    } else if *QUIET.read().unwrap() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(len)
+    }
+}
+
+/// Observer for the command line: status lines, progress bars and --json events
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CliObserver;
+
+impl Observer for CliObserver {
+    fn status(&self, message: &str) {
+        status(message);
+    }
+
+    fn event(&self, name: &str, data: Value) {
+        emit(name, data);
+    }
+
+    fn progress(&self, task: &'static str, total: u64) -> Box<dyn Progress> {
+        Box::new(progress_bar(total, task))
+    }
+}
+
+impl Progress for ProgressBar {
+    fn inc(&self, delta: u64) {
+        ProgressBar::inc(self, delta);
+    }
+
+    fn finish(&self) {
+        ProgressBar::finish(self);
+    }
+}
@@ -1,5 +1,9 @@
+use crate::config::{expand_tilde, home_dir};
+use crate::errors::{MetabobError, Result};
+use log::warn;
 use serde::{Deserialize, Serialize};
-use std::{env, fs, path::Path};
+use solana_program::pubkey::Pubkey;
+use std::{fs, num::NonZeroU32, path::PathBuf, str::FromStr};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SolanaConfig {
@@ -8,30 +12,103 @@ pub struct SolanaConfig {
     pub commitment: String,
 }
 
-//from metaboss
-pub fn parse_solana_config() -> Option<SolanaConfig> {
-    let home = if cfg!(unix) {
-        env::var_os("HOME").expect("Couldn't find UNIX home key.")
-    } else if cfg!(windows) {
-        let drive = env::var_os("HOMEDRIVE").expect("Couldn't find Windows home drive key.");
-        let path = env::var_os("HOMEPATH").expect("Couldn't find Windows home path key.");
-        Path::new(&drive).join(&path).as_os_str().to_owned()
-    } else if cfg!(target_os = "macos") {
-        env::var_os("HOME").expect("Couldn't find MacOS home key.")
-    } else {
-        panic!("Unsupported OS!");
+/// ~/.config/solana/cli/config.yml, same place the Solana CLI looks
+pub fn default_solana_config_path() -> Option<PathBuf> {
+    Some(
+        home_dir()?
+            .join(".config")
+            .join("solana")
+            .join("cli")
+            .join("config.yml"),
+    )
+}
+
+/// Reads the Solana CLI config from `path` (--config or SOLANA_CONFIG) or the default location.
+/// A missing default config isn't an error, a missing explicit one is
+pub fn parse_solana_config(path: Option<&str>) -> Result<Option<SolanaConfig>> {
+    let (config_path, explicit) = match path {
+        Some(path) => (expand_tilde(path), true),
+        None => match default_solana_config_path() {
+            Some(path) => (path, false),
+            None => {
+                warn!("Couldn't find a home directory to look for the Solana config in");
+                return Ok(None);
+            }
+        },
     };
 
-    let config_path = Path::new(&home)
-        .join(".config")
-        .join("solana")
-        .join("cli")
-        .join("config.yml");
+    if !config_path.exists() {
+        return if explicit {
+            Err(MetabobError::config(format!(
+                "Solana config {} doesn't exist",
+                config_path.display()
+            )))
+        } else {
+            Ok(None)
+        };
+    }
 
-    let conf_file = match fs::File::open(config_path) {
-        Ok(f) => f,
-        Err(_) => return None,
-    };
+    let conf_file = fs::File::open(&config_path).map_err(MetabobError::io(&config_path))?;
+    let mut config: SolanaConfig = serde_yaml::from_reader(&conf_file).map_err(|e| {
+        MetabobError::config_with_source(
+            format!("Couldn't parse Solana config {}", config_path.display()),
+            e,
+        )
+    })?;
+
+    // Relative keypair paths are relative to the config file, not wherever metabob was run from
+    let keypair_path = expand_tilde(&config.keypair_path);
+    config.keypair_path = match config_path.parent() {
+        Some(dir) if keypair_path.is_relative() => dir.join(keypair_path),
+        _ => keypair_path,
+    }
+    .to_string_lossy()
+    .into_owned();
+
+    Ok(Some(config))
+}
+
+pub fn parse_pubkey(value: &str) -> Result<Pubkey> {
+    Pubkey::from_str(value).map_err(|source| MetabobError::InvalidPubkey {
+        value: value.to_string(),
+        source,
+    })
+}
+
+/// Requests per second for a rate limit. Zero would mean never sending anything, so it's
+/// turned away here rather than quietly treated as 1
+pub fn parse_rps(value: &str) -> Result<NonZeroU32> {
+    let rps: u32 = value.trim().parse().map_err(|_| {
+        MetabobError::Validation(format!("Requests per second isn't a number: {}", value))
+    })?;
+    NonZeroU32::new(rps).ok_or_else(|| {
+        MetabobError::Validation(String::from("Requests per second has to be at least 1"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::EndpointConfig;
+
+    #[test]
+    fn rps_has_to_be_a_positive_number() {
+        assert_eq!(parse_rps("10").unwrap().get(), 10);
+        assert!(matches!(parse_rps("0"), Err(MetabobError::Validation(_))));
+        assert!(matches!(parse_rps("-1"), Err(MetabobError::Validation(_))));
+        assert!(matches!(
+            parse_rps("fast"),
+            Err(MetabobError::Validation(_))
+        ));
+    }
 
-    serde_yaml::from_reader(&conf_file).ok()
+    #[test]
+    fn endpoint_rps_of_zero_is_rejected() {
+        let endpoint: EndpointConfig = "http://localhost:8899,rps=5".parse().unwrap();
+        assert_eq!(endpoint.rps, NonZeroU32::new(5));
+        assert!(matches!(
+            "http://localhost:8899,rps=0".parse::<EndpointConfig>(),
+            Err(MetabobError::Validation(_))
+        ));
+    }
 }
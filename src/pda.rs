@@ -0,0 +1,203 @@
+use crate::constants::{CANDY_MACHINE_V2_PROGRAM_ID, GUMDROP_PROGRAM_ID};
+use crate::errors::{MetabobError, Result};
+use mpl_token_metadata::ID as TOKEN_METADATA_PROGRAM_ID;
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use spl_token::ID as TOKEN_PROGRAM_ID;
+
+/// How many prints of a master edition each edition marker account keeps track of
+pub const EDITION_MARKER_BIT_SIZE: u64 = 248;
+
+pub fn get_metadata_pda(mint_pubkey: &Pubkey) -> Pubkey {
+    find_metadata_pda(mint_pubkey).0
+}
+
+/// The edition account of a master edition NFT, or of a print made from one
+pub fn get_edition_pda(mint_pubkey: &Pubkey) -> Pubkey {
+    find_edition_pda(mint_pubkey).0
+}
+
+pub fn find_metadata_pda(mint: &Pubkey) -> (Pubkey, u8) {
+    find_metadata_program_address(mint, &[])
+}
+
+pub fn find_edition_pda(mint: &Pubkey) -> (Pubkey, u8) {
+    find_metadata_program_address(mint, &[b"edition"])
+}
+
+/// The marker recording which prints of a master edition have been made, for the range
+/// `edition` falls in. `mint` is the master edition's mint
+pub fn find_edition_marker_pda(mint: &Pubkey, edition: u64) -> (Pubkey, u8) {
+    let marker = (edition / EDITION_MARKER_BIT_SIZE).to_string();
+    find_metadata_program_address(mint, &[b"edition", marker.as_bytes()])
+}
+
+/// Proof that `authority` may verify items into the collection of `mint`
+pub fn find_collection_authority_record_pda(mint: &Pubkey, authority: &Pubkey) -> (Pubkey, u8) {
+    find_metadata_program_address(mint, &[b"collection_authority", authority.as_ref()])
+}
+
+/// Proof that `authority` may spend uses of `mint`
+pub fn find_use_authority_record_pda(mint: &Pubkey, authority: &Pubkey) -> (Pubkey, u8) {
+    find_metadata_program_address(mint, &[b"user", authority.as_ref()])
+}
+
+/// The state a programmable NFT keeps for one of its token accounts
+pub fn find_token_record_pda(mint: &Pubkey, token_account: &Pubkey) -> (Pubkey, u8) {
+    find_metadata_program_address(mint, &[b"token_record", token_account.as_ref()])
+}
+
+/// `owner`'s associated token account for `mint`
+pub fn find_associated_token_address(owner: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), TOKEN_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &spl_associated_token_account::id(),
+    )
+}
+
+/// The first creator of every NFT a v2 candy machine mints, which signs for it
+pub fn find_candy_machine_creator_pda(candy_machine: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"candy_machine", candy_machine.as_ref()],
+        &CANDY_MACHINE_V2_PROGRAM_ID,
+    )
+}
+
+pub fn find_gumdrop_distributor_pda(base: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"MerkleDistributor", base.as_ref()], &GUMDROP_PROGRAM_ID)
+}
+
+/// The distributor's wallet, which is the creator and payer for candy machine claims
+pub fn find_gumdrop_wallet_pda(distributor: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"Wallet", distributor.as_ref()], &GUMDROP_PROGRAM_ID)
+}
+
+/// Every token metadata PDA starts with "metadata", the program id and the mint
+fn find_metadata_program_address(mint: &Pubkey, rest: &[&[u8]]) -> (Pubkey, u8) {
+    let mut seeds: Vec<&[u8]> = vec![
+        b"metadata",
+        TOKEN_METADATA_PROGRAM_ID.as_ref(),
+        mint.as_ref(),
+    ];
+    seeds.extend_from_slice(rest);
+    Pubkey::find_program_address(&seeds, &TOKEN_METADATA_PROGRAM_ID)
+}
+
+/// What to derive addresses from. Every PDA whose seeds are all here gets derived
+#[derive(Debug, Clone, Default)]
+pub struct PdaSeeds {
+    pub mint: Option<Pubkey>,
+    /// Print number, for the edition marker
+    pub edition: Option<u64>,
+    /// Collection or use authority
+    pub authority: Option<Pubkey>,
+    /// Wallet, for its associated token account
+    pub owner: Option<Pubkey>,
+    /// For the token record. Defaults to the owner's associated token account
+    pub token_account: Option<Pubkey>,
+    pub candy_machine: Option<Pubkey>,
+    /// Gumdrop distributor base
+    pub base: Option<Pubkey>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Pda {
+    pub name: &'static str,
+    pub address: String,
+    pub bump: u8,
+    pub program: String,
+}
+
+impl Pda {
+    fn new(name: &'static str, (address, bump): (Pubkey, u8), program: &Pubkey) -> Pda {
+        Pda {
+            name,
+            address: address.to_string(),
+            bump,
+            program: program.to_string(),
+        }
+    }
+}
+
+/// Derives every PDA the seeds are enough for, in a fixed order
+pub fn derive_pdas(seeds: &PdaSeeds) -> Result<Vec<Pda>> {
+    let mut pdas = Vec::new();
+    let metadata = &TOKEN_METADATA_PROGRAM_ID;
+
+    if let Some(mint) = &seeds.mint {
+        pdas.push(Pda::new("metadata", find_metadata_pda(mint), metadata));
+        pdas.push(Pda::new("edition", find_edition_pda(mint), metadata));
+        if let Some(edition) = seeds.edition {
+            pdas.push(Pda::new(
+                "edition_marker",
+                find_edition_marker_pda(mint, edition),
+                metadata,
+            ));
+        }
+        if let Some(authority) = &seeds.authority {
+            pdas.push(Pda::new(
+                "collection_authority_record",
+                find_collection_authority_record_pda(mint, authority),
+                metadata,
+            ));
+            pdas.push(Pda::new(
+                "use_authority_record",
+                find_use_authority_record_pda(mint, authority),
+                metadata,
+            ));
+        }
+        let mut token_account = seeds.token_account;
+        if let Some(owner) = &seeds.owner {
+            let ata = find_associated_token_address(owner, mint);
+            pdas.push(Pda::new(
+                "associated_token_account",
+                ata,
+                &spl_associated_token_account::id(),
+            ));
+            token_account = token_account.or(Some(ata.0));
+        }
+        if let Some(token_account) = &token_account {
+            pdas.push(Pda::new(
+                "token_record",
+                find_token_record_pda(mint, token_account),
+                metadata,
+            ));
+        }
+    } else if seeds.edition.is_some()
+        || seeds.authority.is_some()
+        || seeds.owner.is_some()
+        || seeds.token_account.is_some()
+    {
+        return Err(MetabobError::Validation(String::from(
+            "A mint is needed to derive from an edition, authority, owner or token account",
+        )));
+    }
+
+    if let Some(candy_machine) = &seeds.candy_machine {
+        pdas.push(Pda::new(
+            "candy_machine_creator",
+            find_candy_machine_creator_pda(candy_machine),
+            &CANDY_MACHINE_V2_PROGRAM_ID,
+        ));
+    }
+    if let Some(base) = &seeds.base {
+        let distributor = find_gumdrop_distributor_pda(base);
+        pdas.push(Pda::new(
+            "gumdrop_distributor",
+            distributor,
+            &GUMDROP_PROGRAM_ID,
+        ));
+        pdas.push(Pda::new(
+            "gumdrop_wallet",
+            find_gumdrop_wallet_pda(&distributor.0),
+            &GUMDROP_PROGRAM_ID,
+        ));
+    }
+
+    if pdas.is_empty() {
+        return Err(MetabobError::Validation(String::from(
+            "Pass a mint, candy machine or gumdrop base to derive addresses from",
+        )));
+    }
+    Ok(pdas)
+}
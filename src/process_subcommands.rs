@@ -1,36 +1,283 @@
-use crate::gumdrop::make_list;
-use crate::opt::GumdropSubcommands;
+use crate::config::{expand_tilde, load_config_file, Settings};
+use crate::constants::{
+    ACCOUNT_INFO_FILE, DEFAULT_MAX_LOGO_BYTES, DEFAULT_THUMBNAIL_SIZE, FAILED_MINTS_FILE,
+    LOGO_REPORT_FILE, MERGED_TOKEN_LIST_FILE, MERGE_CONFLICTS_FILE, MINT_INFO_FILE,
+    NO_URI_TOKEN_LIST_FILE, REJECTED_TOKENS_FILE, TOKEN_LIST_DIFF_FILE, TOKEN_LIST_FILE,
+    URI_TOKEN_LIST_FILE,
+};
+use crate::db::{transaction, Store, StoreTable};
+use crate::errors::{MetabobError, Result};
+use crate::filter::{filter_token_entries, FilterRules, Rejection};
+use crate::gumdrop::{
+    make_claims, make_list, read_claim, read_distribution, read_handles, read_whitelist,
+    validate_list, verify_claim, ClaimType, HandleType, ValidationReport,
+};
+use crate::inspect::inspect;
+use crate::logos::{check_logos, LogoOptions};
+use crate::opt::{DbSubcommands, GumdropSubcommands, PdaOptions};
+use crate::output::{emit, is_json, CliObserver};
+use crate::parse::parse_pubkey;
+use crate::pda::{derive_pdas, PdaSeeds};
+use crate::rpc::RpcPool;
+use crate::sink::{save_json, save_json_pretty, DirSink, NullSink, OutputSink};
+use crate::status;
+use crate::stream::{count_ndjson, read_ndjson, JsonArrayWriter, NdjsonWriter};
+use crate::token_list::{
+    diff_token_lists, merge_token_lists, read_token_list, MergeRules, TokenListDiff, TokenListFile,
+};
 use crate::{
-    metadata::{count_creators, sign_all},
+    metadata::{count_creators, sign_accounts},
     opt::{MetadataSubcommands, SplSubcommands},
     spl::{
-        do_everything, do_stuff, get_metadata_accounts, get_mint_accounts, get_token_entries,
-        parse_token_uri,
+        do_everything, do_stuff, get_metadata_accounts, get_token_entries, parse_token_uri,
+        refresh, scan_mint_accounts, AccountStruct, Snapshot, TokenListEntry,
     },
 };
-use anyhow::Result;
-use solana_client::rpc_client::RpcClient;
+use log::info;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Signer},
+};
+use std::{
+    fs::File,
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
 
 pub fn process_spl(
-    client: &RpcClient,
-    heavy_client: &RpcClient,
+    client: &RpcPool,
+    settings: &Settings,
     subcommands: SplSubcommands,
 ) -> Result<()> {
+    let dir_sink = DirSink::new(&settings.output_dir);
+    let save_sink = |no_save: bool| -> &dyn OutputSink {
+        if no_save {
+            &NullSink
+        } else {
+            &dir_sink
+        }
+    };
+    let store = open_store(settings)?;
     match subcommands {
-        SplSubcommands::DoEverything { no_save } => {
-            do_everything(client, heavy_client, no_save)?;
+        SplSubcommands::DoEverything {
+            no_save,
+            commitment,
+        } => {
+            let run = do_everything(
+                client,
+                commitment.unwrap_or(client.commitment()),
+                &CliObserver,
+                save_sink(no_save),
+                &dir_sink,
+                store.as_ref(),
+            )?;
+            save_failed_mints(&dir_sink, &run.metadata.failed_mints)?;
         }
-        SplSubcommands::GetMints { no_save } => {
-            get_mint_accounts(client, no_save)?;
+        SplSubcommands::Refresh { commitment } => {
+            let previous = match &store {
+                Some(store) => {
+                    status!(CliObserver, "Reading the last run from the database...");
+                    let failed_mints = store.failed_mints()?;
+                    store.with_mints(|mints| {
+                        store
+                            .with_accounts(|accounts| Snapshot::load(mints, accounts, failed_mints))
+                    })?
+                }
+                None => {
+                    let (_, mints) = load_ndjson(settings, MINT_INFO_FILE)?;
+                    let (_, accounts) = load_ndjson(settings, ACCOUNT_INFO_FILE)?;
+                    Snapshot::load(mints, accounts, load_failed_mints(settings)?)?
+                }
+            };
+            let run = refresh(
+                client,
+                commitment.unwrap_or(client.commitment()),
+                &CliObserver,
+                previous,
+                &dir_sink,
+                &dir_sink,
+                store.as_ref(),
+            )?;
+            save_failed_mints(&dir_sink, &run.metadata.failed_mints)?;
+            save(&dir_sink, TOKEN_LIST_DIFF_FILE, &run.diff)?;
         }
-        SplSubcommands::GetMetadataAccounts { no_save } => {
-            get_metadata_accounts(client, None, no_save)?;
+        SplSubcommands::GetMints {
+            no_save,
+            commitment,
+        } => {
+            let mut writer = ndjson_writer(save_sink(no_save), MINT_INFO_FILE)?;
+            transaction(store.as_ref(), || {
+                scan_mint_accounts(
+                    client,
+                    commitment.unwrap_or(client.commitment()),
+                    &CliObserver,
+                    |shard| {
+                        for mint in &shard {
+                            writer.write(mint)?;
+                            if let Some(store) = &store {
+                                store.upsert_mint(mint)?;
+                            }
+                        }
+                        Ok(())
+                    },
+                )
+                .map(|_| ())
+            })?;
+            finish_ndjson(writer, MINT_INFO_FILE, no_save)?;
+        }
+        SplSubcommands::GetMetadataAccounts {
+            no_save,
+            commitment,
+        } => {
+            let commitment = commitment.unwrap_or(client.commitment());
+            let mut writer = ndjson_writer(save_sink(no_save), ACCOUNT_INFO_FILE)?;
+            let scan = transaction(store.as_ref(), || {
+                let on_account = |account: AccountStruct| {
+                    writer.write(&account)?;
+                    match &store {
+                        Some(store) => store.upsert_account(&account),
+                        None => Ok(()),
+                    }
+                };
+                let scan = match &store {
+                    Some(store) => {
+                        let total = store_count(store, StoreTable::Mints)?;
+                        store.with_mints(|mints| {
+                            get_metadata_accounts(
+                                client,
+                                mints,
+                                total,
+                                commitment,
+                                &CliObserver,
+                                on_account,
+                            )
+                        })?
+                    }
+                    None => {
+                        let (total, mints) = load_ndjson(settings, MINT_INFO_FILE)?;
+                        get_metadata_accounts(
+                            client,
+                            mints,
+                            total,
+                            commitment,
+                            &CliObserver,
+                            on_account,
+                        )?
+                    }
+                };
+                if let Some(store) = &store {
+                    store.upsert_failed_mints(&scan.failed_mints)?;
+                }
+                Ok(scan)
+            })?;
+            finish_ndjson(writer, ACCOUNT_INFO_FILE, no_save)?;
+            save_failed_mints(&dir_sink, &scan.failed_mints)?;
         }
         SplSubcommands::GetTokenList { no_save } => {
-            get_token_entries(None, no_save)?;
+            let mut writer = ndjson_writer(save_sink(no_save), TOKEN_LIST_FILE)?;
+            transaction(store.as_ref(), || {
+                let on_entry = |entry: TokenListEntry| {
+                    writer.write(&entry)?;
+                    match &store {
+                        Some(store) => store.upsert_token_entry(&entry),
+                        None => Ok(()),
+                    }
+                };
+                match &store {
+                    Some(store) => {
+                        store_count(store, StoreTable::Metadata)?;
+                        store.with_accounts(|accounts| {
+                            get_token_entries(accounts, &CliObserver, on_entry)
+                        })
+                    }
+                    None => {
+                        let (_, accounts) = load_ndjson(settings, ACCOUNT_INFO_FILE)?;
+                        get_token_entries(accounts, &CliObserver, on_entry)
+                    }
+                }
+            })?;
+            finish_ndjson(writer, TOKEN_LIST_FILE, no_save)?;
+        }
+        SplSubcommands::Filter { rules, no_save } => {
+            let rules: FilterRules = match rules {
+                Some(path) => load_config_file(&expand_tilde(&path), "filter rules")?,
+                None => FilterRules::default(),
+            };
+            let mut writer = ndjson_writer(save_sink(no_save), TOKEN_LIST_FILE)?;
+            let mut rejected_writer =
+                JsonArrayWriter::new(dir_sink.create(REJECTED_TOKENS_FILE)?, REJECTED_TOKENS_FILE);
+            transaction(store.as_ref(), || {
+                let accepted = |entry: TokenListEntry| {
+                    writer.write(&entry)?;
+                    match &store {
+                        Some(store) => {
+                            store.remove_rejection(&entry.address)?;
+                            store.upsert_token_entry(&entry)
+                        }
+                        None => Ok(()),
+                    }
+                };
+                let rejected = |rejection: Rejection| {
+                    rejected_writer.write(&rejection)?;
+                    match &store {
+                        Some(store) => store.upsert_rejection(&rejection),
+                        None => Ok(()),
+                    }
+                };
+                match &store {
+                    Some(store) => {
+                        store_count(store, StoreTable::Metadata)?;
+                        store.with_accounts(|accounts| {
+                            filter_token_entries(accounts, &rules, &CliObserver, accepted, rejected)
+                        })
+                    }
+                    None => {
+                        let (_, accounts) = load_ndjson(settings, ACCOUNT_INFO_FILE)?;
+                        filter_token_entries(accounts, &rules, &CliObserver, accepted, rejected)
+                    }
+                }
+            })?;
+            finish_ndjson(writer, TOKEN_LIST_FILE, no_save)?;
+            let rejected = rejected_writer.finish()?;
+            status!(
+                CliObserver,
+                "Saved {} rejected tokens to {}",
+                rejected,
+                REJECTED_TOKENS_FILE
+            );
         }
         SplSubcommands::ParseTokenList { no_save } => {
-            parse_token_uri(None, no_save)?;
+            let sink = save_sink(no_save);
+            let mut uri_writer =
+                JsonArrayWriter::new(sink.create(URI_TOKEN_LIST_FILE)?, URI_TOKEN_LIST_FILE);
+            let mut no_uri_writer =
+                JsonArrayWriter::new(sink.create(NO_URI_TOKEN_LIST_FILE)?, NO_URI_TOKEN_LIST_FILE);
+            let with_uri = |entry| uri_writer.write(&entry);
+            let without_uri = |entry| no_uri_writer.write(&entry);
+            match &store {
+                Some(store) => {
+                    store_count(store, StoreTable::TokenList)?;
+                    store.with_token_entries(|token_list| {
+                        parse_token_uri(token_list, &CliObserver, with_uri, without_uri)
+                    })?;
+                }
+                None => {
+                    let (_, token_list) = load_ndjson(settings, TOKEN_LIST_FILE)?;
+                    parse_token_uri(token_list, &CliObserver, with_uri, without_uri)?;
+                }
+            }
+            uri_writer.finish()?;
+            no_uri_writer.finish()?;
+        }
+        subcommands @ (SplSubcommands::Diff { .. }
+        | SplSubcommands::Merge { .. }
+        | SplSubcommands::CheckLogos { .. }) => {
+            process_token_lists(settings, subcommands)?;
         }
         SplSubcommands::DoStuff => {
             do_stuff()?;
@@ -40,30 +287,589 @@ pub fn process_spl(
     Ok(())
 }
 
-pub fn process_metadata(client: &RpcClient, subcommands: MetadataSubcommands) -> Result<()> {
+/// The spl commands that only work on token list files, so they don't need an rpc. Any other
+/// subcommand is an error
+pub fn process_token_lists(settings: &Settings, subcommands: SplSubcommands) -> Result<()> {
     match subcommands {
-        MetadataSubcommands::SignAll { keypair } => {
-            sign_all(client, keypair)?;
+        SplSubcommands::Diff { old, new, out } => {
+            let diff = diff_token_lists(
+                read_token_list(Path::new(&old))?,
+                read_token_list(Path::new(&new))?,
+            );
+            print_token_list_diff(&diff);
+            if let Some(out) = out {
+                let (sink, name) = file_sink(Path::new(&out));
+                save_pretty(&sink, &name, &diff)?;
+            }
         }
-        MetadataSubcommands::CountCreators { creator } => {
-            count_creators(client, creator)?;
+        SplSubcommands::Merge {
+            curated,
+            generated,
+            prefer,
+            out,
+        } => {
+            let generated = generated
+                .map(PathBuf::from)
+                .unwrap_or_else(|| settings.output_dir.join(TOKEN_LIST_FILE));
+            let rules = prefer
+                .into_iter()
+                .fold(MergeRules::default(), MergeRules::prefer);
+            let curated = TokenListFile::read(Path::new(&curated))?;
+            let report = merge_token_lists(
+                curated.tokens().to_vec(),
+                read_token_list(&generated)?,
+                &rules,
+            );
+            status!(
+                CliObserver,
+                "{} tokens only in the curated list, {} only in the generated list and {} in both",
+                report.curated_only,
+                report.chain_only,
+                report.both
+            );
+            for conflict in &report.conflicts {
+                info!(
+                    "{} on chain {} {}: curated {:?}, chain {:?}, using {:?}",
+                    conflict.address,
+                    conflict.chain_id,
+                    conflict.field,
+                    conflict.curated,
+                    conflict.chain,
+                    conflict.winner
+                );
+            }
+            status!(
+                CliObserver,
+                "{} fields differed between the lists",
+                report.conflicts.len()
+            );
+            emit(
+                "merge",
+                json!({
+                    "merged": report.merged.len(),
+                    "curated_only": report.curated_only,
+                    "chain_only": report.chain_only,
+                    "both": report.both,
+                    "conflicts": report.conflicts.len(),
+                }),
+            );
+            let dir_sink = DirSink::new(&settings.output_dir);
+            if !report.conflicts.is_empty() {
+                save_pretty(&dir_sink, MERGE_CONFLICTS_FILE, &report.conflicts)?;
+            }
+            // Saved in the curated list's shape, keeping its name, version and so on
+            let merged = curated.with_tokens(report.merged);
+            match out {
+                Some(out) => {
+                    let (sink, name) = file_sink(Path::new(&out));
+                    save_pretty(&sink, &name, &merged)?;
+                }
+                None => save_pretty(&dir_sink, MERGED_TOKEN_LIST_FILE, &merged)?,
+            }
+        }
+        SplSubcommands::CheckLogos {
+            token_list,
+            max_size,
+            thumbnails,
+            thumbnail_size,
+            out,
+        } => {
+            let token_list = token_list
+                .map(PathBuf::from)
+                .unwrap_or_else(|| settings.output_dir.join(URI_TOKEN_LIST_FILE));
+            let options = LogoOptions {
+                max_bytes: max_size.unwrap_or(DEFAULT_MAX_LOGO_BYTES),
+                timeout: settings.timeout,
+                in_flight: settings.in_flight,
+                thumbnail_dir: thumbnails.map(|dir| expand_tilde(&dir)),
+                thumbnail_size: thumbnail_size.unwrap_or(DEFAULT_THUMBNAIL_SIZE),
+            };
+            let (sink, name) = match out {
+                Some(out) => file_sink(Path::new(&out)),
+                None => (
+                    DirSink::new(&settings.output_dir),
+                    String::from(LOGO_REPORT_FILE),
+                ),
+            };
+            let mut writer = JsonArrayWriter::new(sink.create(&name)?, &name);
+            check_logos(
+                read_token_list(&token_list)?,
+                &options,
+                &CliObserver,
+                |check| writer.write(&check),
+            )?;
+            let saved = writer.finish()?;
+            status!(CliObserver, "Saved {} logo checks to {}", saved, name);
+        }
+        subcommands => {
+            return Err(MetabobError::Validation(format!(
+            "{:?} needs an rpc. Only diff, merge and check_logos work on token list files alone",
+            subcommands
+        )))
         }
     }
 
     Ok(())
 }
 
-pub fn process_gumdrop(subcommands: GumdropSubcommands) -> Result<()> {
+pub fn process_metadata(
+    client: &RpcPool,
+    settings: &Settings,
+    subcommands: MetadataSubcommands,
+) -> Result<()> {
+    let sink = DirSink::new(&settings.output_dir);
+    let store = open_store(settings)?;
+    match subcommands {
+        MetadataSubcommands::SignAll {
+            keypair,
+            commitment,
+        } => {
+            let keypair_path = keypair.or_else(|| settings.keypair.clone()).ok_or_else(|| {
+                MetabobError::config(
+                    "You didn't pass in a keypair and there isn't one in your profile or Solana config",
+                )
+            })?;
+            let keypair = read_keypair_file(&keypair_path).map_err(|e| MetabobError::Keypair {
+                path: keypair_path.clone(),
+                message: e.to_string(),
+            })?;
+            let creator = keypair.pubkey();
+            let commitment = commitment.unwrap_or(client.commitment());
+            let mut unverified = count_creators(client, &creator, commitment, &CliObserver)?;
+            // A slow RPC can still show accounts we already signed as unverified, so don't pay
+            // to sign them twice
+            if let Some(store) = &store {
+                let signed = store.signed_metadata(&creator)?;
+                let before = unverified.len();
+                unverified.retain(|metadata| !signed.contains(metadata));
+                if unverified.len() < before {
+                    status!(
+                        CliObserver,
+                        "Skipping {} metadata accounts the database has as already signed",
+                        before - unverified.len()
+                    );
+                }
+                store.in_transaction(|| store.upsert_unverified(&creator, &unverified))?;
+            }
+            let report = sign_accounts(
+                client,
+                Arc::new(keypair),
+                unverified,
+                commitment,
+                settings.priority_fee,
+                &CliObserver,
+                // Saved one at a time, so a run that dies partway can pick up where it left off
+                |metadata, result| match (&store, result) {
+                    (Some(store), Ok(signature)) => {
+                        store.upsert_signed(&creator, metadata, signature)
+                    }
+                    (Some(store), Err(error)) => {
+                        store.upsert_sign_failed(&creator, metadata, error)
+                    }
+                    (None, _) => Ok(()),
+                },
+            )?;
+            save_unverified(&sink, &report.unverified)?;
+        }
+        MetadataSubcommands::CountCreators {
+            creator,
+            commitment,
+        } => {
+            let creator = parse_pubkey(&creator)?;
+            let unverified = count_creators(
+                client,
+                &creator,
+                commitment.unwrap_or(client.commitment()),
+                &CliObserver,
+            )?;
+            if let Some(store) = &store {
+                store.in_transaction(|| store.upsert_unverified(&creator, &unverified))?;
+            }
+            save_unverified(&sink, &unverified)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn process_inspect(
+    client: &RpcPool,
+    pubkey: &str,
+    commitment: Option<CommitmentConfig>,
+) -> Result<()> {
+    let address = parse_pubkey(pubkey)?;
+    let inspection = inspect(client, &address, commitment.unwrap_or(client.commitment()))?;
+    if is_json() {
+        emit("inspect", &inspection);
+    } else {
+        let pretty =
+            serde_json::to_string_pretty(&inspection).map_err(MetabobError::json("stdout"))?;
+        println!("{}", pretty);
+    }
+    Ok(())
+}
+
+pub fn process_pda(options: &PdaOptions) -> Result<()> {
+    let parse = |pubkey: &Option<String>| pubkey.as_deref().map(parse_pubkey).transpose();
+    let seeds = PdaSeeds {
+        mint: parse(&options.mint)?,
+        edition: options.edition,
+        authority: parse(&options.authority)?,
+        owner: parse(&options.owner)?,
+        token_account: parse(&options.token_account)?,
+        candy_machine: parse(&options.candy_machine)?,
+        base: parse(&options.base)?,
+    };
+    let pdas = derive_pdas(&seeds)?;
+    if is_json() {
+        for pda in pdas.iter() {
+            emit("pda", pda);
+        }
+    } else {
+        let width = pdas.iter().map(|pda| pda.name.len()).max().unwrap_or(0);
+        for pda in pdas.iter() {
+            println!(
+                "{:width$}  {:44}  bump {}",
+                pda.name,
+                pda.address,
+                pda.bump,
+                width = width
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn process_db(settings: &Settings, subcommands: DbSubcommands) -> Result<()> {
+    let store = open_store(settings)?.ok_or_else(|| {
+        MetabobError::config("Pass --db or set db in your profile to use the db commands")
+    })?;
+    match subcommands {
+        DbSubcommands::Query { sql } => {
+            let result = store.query(&sql)?;
+            if is_json() {
+                for row in result.rows.iter() {
+                    let row: Map<String, Value> =
+                        result.columns.iter().cloned().zip(row.clone()).collect();
+                    emit("row", row);
+                }
+            } else if !result.columns.is_empty() {
+                println!("{}", result.columns.join("\t"));
+                for row in &result.rows {
+                    let cells: Vec<String> = row
+                        .iter()
+                        .map(|value| match value {
+                            Value::Null => String::from("NULL"),
+                            Value::String(value) => value.clone(),
+                            value => value.to_string(),
+                        })
+                        .collect();
+                    println!("{}", cells.join("\t"));
+                }
+            }
+            if result.columns.is_empty() {
+                status!(CliObserver, "{} rows changed", result.changes);
+            } else {
+                status!(CliObserver, "{} rows", result.rows.len());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn process_gumdrop(
+    client: &RpcPool,
+    settings: &Settings,
+    subcommands: GumdropSubcommands,
+) -> Result<()> {
     match subcommands {
         GumdropSubcommands::MakeList {
             number,
             amount,
+            start_edition,
             repeat,
-            pubkey_path,
+            handle_path,
+            handle_type,
+            claim_type,
+            seed,
+        } => {
+            let handles = match handle_path {
+                Some(path) => read_handles(&path, handle_type)?,
+                None => vec![],
+            };
+            make_list(
+                number,
+                amount,
+                start_edition,
+                repeat,
+                handles,
+                handle_type,
+                claim_type,
+                seed,
+                &CliObserver,
+                &DirSink::new(&settings.output_dir).no_overwrite(),
+            )?;
+        }
+        GumdropSubcommands::BuildTree {
+            distribution,
+            target,
+            out_dir,
+            handle_type,
+            claim_type,
+            base,
+        } => {
+            let whitelist = read_distribution(&distribution, claim_type)?;
+            let base = base.map(|base| parse_pubkey(&base)).transpose()?;
+            let sink = match out_dir {
+                Some(out_dir) => DirSink::new(out_dir),
+                None => DirSink::new(settings.output_dir.join("claims")),
+            };
+            status!(CliObserver, "Claim files go in {}", sink.dir().display());
+            make_claims(
+                &whitelist,
+                &parse_pubkey(&target)?,
+                handle_type,
+                claim_type,
+                base.as_ref(),
+                &CliObserver,
+                &sink,
+            )?;
+        }
+        GumdropSubcommands::VerifyProof { claim, root } => {
+            let claim = read_claim(&claim)?;
+            if !verify_claim(&claim, root.as_deref(), &CliObserver)? {
+                return Err(MetabobError::Validation(String::from(
+                    "Invalid merkle proof",
+                )));
+            }
+        }
+        GumdropSubcommands::Validate {
+            distribution,
+            handle_type,
+            claim_type,
+            mint,
+            budget,
         } => {
-            make_list(number, amount, repeat, pubkey_path)?;
+            let whitelist = read_whitelist(&distribution)?;
+            let mint = mint.map(|mint| parse_pubkey(&mint)).transpose()?;
+            let report = validate_list(
+                client,
+                &whitelist,
+                handle_type,
+                claim_type,
+                mint.as_ref(),
+                budget,
+                &CliObserver,
+            )?;
+            print_validation_report(&report, handle_type, claim_type);
+            if !report.is_valid() {
+                return Err(MetabobError::Validation(String::from(
+                    "Distribution file has problems",
+                )));
+            }
+            status!(CliObserver, "{} looks good!", distribution);
         }
     }
 
     Ok(())
 }
+
+fn open_store(settings: &Settings) -> Result<Option<Store>> {
+    settings
+        .db
+        .as_ref()
+        .map(|path| {
+            status!(CliObserver, "Database: {}", path.display());
+            Store::open(path)
+        })
+        .transpose()
+}
+
+/// Counts what a step is about to read from the database, which is also the progress total
+fn store_count(store: &Store, table: StoreTable) -> Result<u64> {
+    let count = store.count(table)?;
+    status!(
+        CliObserver,
+        "Reading {} {} rows from the database...",
+        count,
+        table
+    );
+    Ok(count)
+}
+
+/// Opens an NDJSON file an earlier step saved in the output dir, returning how many records it
+/// has along with a reader that streams them
+fn load_ndjson<T: DeserializeOwned>(
+    settings: &Settings,
+    name: &str,
+) -> Result<(u64, impl Iterator<Item = Result<T>>)> {
+    let path = settings.output_dir.join(name);
+    status!(CliObserver, "Reading {}...", path.display());
+    Ok((count_ndjson(&path)?, read_ndjson(&path)?))
+}
+
+fn ndjson_writer<'a>(
+    sink: &'a dyn OutputSink,
+    name: &str,
+) -> Result<NdjsonWriter<Box<dyn Write + Send + 'a>>> {
+    Ok(NdjsonWriter::new(sink.create(name)?, name))
+}
+
+fn finish_ndjson<W: Write>(writer: NdjsonWriter<W>, name: &str, no_save: bool) -> Result<()> {
+    let count = writer.finish()?;
+    if !no_save {
+        status!(CliObserver, "Saved {} records to {}", count, name);
+    }
+    Ok(())
+}
+
+fn save<T: Serialize + ?Sized>(sink: &dyn OutputSink, name: &str, value: &T) -> Result<()> {
+    status!(CliObserver, "Saving {}...", name);
+    let start = Instant::now();
+    save_json(sink, name, value)?;
+    let duration = start.elapsed();
+    status!(
+        CliObserver,
+        "Saved {} in {} minutes and {} seconds!",
+        name,
+        duration.as_secs() / 60,
+        duration.as_secs() % 60
+    );
+    Ok(())
+}
+
+/// Mints the last metadata scan couldn't fetch, if it saved any
+fn load_failed_mints(settings: &Settings) -> Result<Vec<Pubkey>> {
+    let path = settings.output_dir.join(FAILED_MINTS_FILE);
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let file = File::open(&path).map_err(MetabobError::io(&path))?;
+    let mints: Vec<String> =
+        serde_json::from_reader(BufReader::new(file)).map_err(MetabobError::json(&path))?;
+    mints.iter().map(|mint| parse_pubkey(mint)).collect()
+}
+
+fn save_pretty<T: Serialize + ?Sized>(sink: &DirSink, name: &str, value: &T) -> Result<()> {
+    save_json_pretty(sink, name, value)?;
+    status!(CliObserver, "Saved {}", sink.path(name).display());
+    Ok(())
+}
+
+/// A sink for the directory a user given file path is in, and the file's name in it
+fn file_sink(path: &Path) -> (DirSink, String) {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    (DirSink::new(dir), name)
+}
+
+fn print_token_list_diff(diff: &TokenListDiff) {
+    if is_json() {
+        emit("token_list_diff", diff);
+        return;
+    }
+    for entry in &diff.added {
+        println!("+ {} {} ({})", entry.address, entry.symbol, entry.name);
+    }
+    for entry in &diff.removed {
+        println!("- {} {} ({})", entry.address, entry.symbol, entry.name);
+    }
+    for change in &diff.changed {
+        for field in &change.changes {
+            println!(
+                "~ {} {}: {:?} -> {:?}",
+                change.address, field.field, field.old, field.new
+            );
+        }
+    }
+    status!(
+        CliObserver,
+        "{} added, {} removed, {} changed",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len()
+    );
+}
+
+/// Saved even with --no-save, so mints we never heard back about can be retried
+fn save_failed_mints(sink: &dyn OutputSink, failed_mints: &[Pubkey]) -> Result<()> {
+    if failed_mints.is_empty() {
+        return Ok(());
+    }
+    let failed_strings: Vec<String> = failed_mints.iter().map(|key| key.to_string()).collect();
+    save(sink, FAILED_MINTS_FILE, &failed_strings)
+}
+
+fn save_unverified(sink: &dyn OutputSink, unverified: &[Pubkey]) -> Result<()> {
+    if unverified.is_empty() {
+        return Ok(());
+    }
+    let strings: Vec<String> = unverified.iter().map(|key| key.to_string()).collect();
+    save(sink, "metadata_list.json", &strings)?;
+    save(sink, "metadata_pubkeys.json", unverified)
+}
+
+fn print_validation_report(
+    report: &ValidationReport,
+    handle_type: HandleType,
+    claim_type: ClaimType,
+) {
+    for (i, handle) in &report.invalid_handles {
+        status!(
+            CliObserver,
+            "Invalid {:?} handle at index {}: {}",
+            handle_type,
+            i,
+            handle
+        );
+    }
+    for (i, handle) in &report.duplicate_handles {
+        status!(CliObserver, "Duplicate handle at index {}: {}", i, handle);
+    }
+    for i in &report.zero_amounts {
+        status!(CliObserver, "Zero amount or edition at index {}", i);
+    }
+    for i in &report.wrong_claim_types {
+        status!(
+            CliObserver,
+            "Claim at index {} isn't a {:?} claim",
+            i,
+            claim_type
+        );
+    }
+    for (i, edition) in &report.duplicate_editions {
+        status!(CliObserver, "Duplicate edition {} at index {}", edition, i);
+    }
+
+    status!(CliObserver, "Recipients: {}", report.recipients);
+    match report.ui_total() {
+        Some(ui_total) => status!(
+            CliObserver,
+            "Total amount: {} ({} tokens)",
+            report.total_amount,
+            ui_total
+        ),
+        None => status!(CliObserver, "Total amount: {}", report.total_amount),
+    }
+    if let (true, Some(supply)) = (report.over_supply(), report.supply) {
+        status!(
+            CliObserver,
+            "Total amount is more than the mint's supply of {}!",
+            supply
+        );
+    }
+    if let (true, Some(budget)) = (report.over_budget(), report.budget) {
+        status!(
+            CliObserver,
+            "Total amount is more than the budget of {}!",
+            budget
+        );
+    }
+}
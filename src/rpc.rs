@@ -0,0 +1,899 @@
+use crate::constants::{
+    RPC_BASE_BACKOFF_MS, RPC_MAX_BACKOFF_MS, RPC_MAX_RETRIES, RPC_POOL_MAX_RETRIES,
+};
+use crate::errors::{MetabobError, Result};
+use crate::limiter::RateLimiter;
+use crate::parse::parse_rps;
+use crate::runtime::runtime;
+use futures::Future;
+use log::{debug, warn};
+use rand::Rng;
+use solana_client::{
+    client_error::{reqwest::StatusCode, ClientError, ClientErrorKind, Result as ClientResult},
+    nonblocking::rpc_client::RpcClient as AsyncRpcClient,
+    rpc_client::RpcClient,
+    rpc_config::{RpcProgramAccountsConfig, RpcSendTransactionConfig},
+    rpc_custom_error::{
+        JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+        JSON_RPC_SERVER_ERROR_SLOT_SKIPPED,
+    },
+    rpc_request::{RpcError, RpcResponseErrorData},
+    rpc_response::Response,
+};
+use solana_sdk::{
+    account::Account,
+    clock::{Slot, MAX_PROCESSING_AGE},
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::Transaction,
+};
+use std::{
+    num::NonZeroU32,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use tokio::sync::Notify;
+
+/// Rough buckets for why an rpc call failed, so we know whether it's worth trying again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcErrorKind {
+    RateLimited,
+    Timeout,
+    NodeBehind,
+    Other,
+}
+
+impl RpcErrorKind {
+    pub fn is_retryable(&self) -> bool {
+        *self != RpcErrorKind::Other
+    }
+}
+
+pub fn classify_error(error: &ClientError) -> RpcErrorKind {
+    match error.kind() {
+        ClientErrorKind::Reqwest(e) => {
+            if e.status() == Some(StatusCode::TOO_MANY_REQUESTS) {
+                RpcErrorKind::RateLimited
+            } else if e.is_timeout() || e.is_connect() {
+                RpcErrorKind::Timeout
+            } else if matches!(e.status(), Some(status) if status.is_server_error()) {
+                RpcErrorKind::NodeBehind
+            } else {
+                RpcErrorKind::Other
+            }
+        }
+        ClientErrorKind::Io(e) if e.kind() == std::io::ErrorKind::TimedOut => RpcErrorKind::Timeout,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, data, .. }) => {
+            match (*code, data) {
+                (_, RpcResponseErrorData::NodeUnhealthy { .. })
+                | (JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY, _)
+                | (JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE, _)
+                | (JSON_RPC_SERVER_ERROR_SLOT_SKIPPED, _) => RpcErrorKind::NodeBehind,
+                (429, _) => RpcErrorKind::RateLimited,
+                _ => RpcErrorKind::Other,
+            }
+        }
+        // Some providers don't bother with a proper status code, so sniff the message too
+        _ => {
+            let message = error.to_string().to_lowercase();
+            if message.contains("429") || message.contains("too many requests") {
+                RpcErrorKind::RateLimited
+            } else if message.contains("timed out")
+                || message.contains("timeout")
+                || message.contains("error sending request")
+                || message.contains("connection")
+            {
+                RpcErrorKind::Timeout
+            } else if message.contains("behind") || message.contains("unhealthy") {
+                RpcErrorKind::NodeBehind
+            } else {
+                RpcErrorKind::Other
+            }
+        }
+    }
+}
+
+/// Exponential backoff from the base delay, capped, with full jitter on the top half so a
+/// pile of threads that got throttled together don't all come back at once
+pub fn backoff_delay(attempt: u32) -> Duration {
+    let max_ms = RPC_BASE_BACKOFF_MS
+        .saturating_mul(2u64.saturating_pow(attempt))
+        .min(RPC_MAX_BACKOFF_MS);
+    Duration::from_millis(rand::thread_rng().gen_range(max_ms / 2..=max_ms))
+}
+
+/// Caps how many calls are in flight at once. The cap is halved whenever the node throttles
+/// us and creeps back up by one after a run of successes
+pub struct AdaptiveConcurrency {
+    limit: AtomicUsize,
+    max: usize,
+    successes: AtomicUsize,
+    in_flight: Mutex<usize>,
+    freed: Condvar,
+    /// Wakes async callers, which can't wait on the condvar without blocking their thread
+    freed_async: Notify,
+}
+
+/// A slot taken by an async call, given back when it's dropped so a call that's cancelled
+/// midway doesn't keep it
+struct Permit<'a>(&'a AdaptiveConcurrency);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+impl AdaptiveConcurrency {
+    pub fn new(max: usize) -> AdaptiveConcurrency {
+        let max = max.max(1);
+        AdaptiveConcurrency {
+            limit: AtomicUsize::new(max),
+            max,
+            successes: AtomicUsize::new(0),
+            in_flight: Mutex::new(0),
+            freed: Condvar::new(),
+            freed_async: Notify::new(),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    fn acquire(&self) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while *in_flight >= self.limit() {
+            in_flight = self.freed.wait(in_flight).unwrap();
+        }
+        *in_flight += 1;
+    }
+
+    async fn acquire_async(&self) -> Permit<'_> {
+        loop {
+            // Made before checking, so a release in between still wakes it
+            let freed = self.freed_async.notified();
+            {
+                let mut in_flight = self.in_flight.lock().unwrap();
+                if *in_flight < self.limit() {
+                    *in_flight += 1;
+                    return Permit(self);
+                }
+            }
+            freed.await;
+        }
+    }
+
+    fn release(&self) {
+        *self.in_flight.lock().unwrap() -= 1;
+        self.freed.notify_one();
+        self.freed_async.notify_waiters();
+    }
+
+    fn on_success(&self) {
+        let successes = self.successes.fetch_add(1, Ordering::Relaxed) + 1;
+        let limit = self.limit();
+        if successes >= limit * 10
+            && limit < self.max
+            && self
+                .limit
+                .compare_exchange(limit, limit + 1, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.successes.store(0, Ordering::Relaxed);
+            self.freed.notify_one();
+            self.freed_async.notify_waiters();
+        }
+    }
+
+    fn on_throttled(&self) {
+        let limit = self.limit();
+        let lowered = (limit / 2).max(1);
+        if self
+            .limit
+            .compare_exchange(limit, lowered, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+            && lowered < limit
+        {
+            self.successes.store(0, Ordering::Relaxed);
+            warn!("RPC is throttling us, dropping concurrency to {}", lowered);
+        }
+    }
+}
+
+/// Wraps an `RpcClient` so every call goes through the shared rate limiter, the adaptive
+/// concurrency cap, and retries with backoff when the error looks temporary. Big scans and
+/// signing go through the nonblocking client instead, with the same limits
+pub struct Rpc {
+    url: String,
+    client: RpcClient,
+    async_client: AsyncRpcClient,
+    limiter: RateLimiter,
+    concurrency: AdaptiveConcurrency,
+    max_retries: u32,
+}
+
+impl Rpc {
+    pub fn new(
+        url: String,
+        timeout: Duration,
+        commitment: CommitmentConfig,
+        limiter: RateLimiter,
+    ) -> Rpc {
+        Rpc {
+            client: RpcClient::new_with_timeout_and_commitment(url.clone(), timeout, commitment),
+            async_client: AsyncRpcClient::new_with_timeout_and_commitment(
+                url.clone(),
+                timeout,
+                commitment,
+            ),
+            url,
+            limiter,
+            concurrency: AdaptiveConcurrency::new(num_cpus::get()),
+            max_retries: RPC_MAX_RETRIES,
+        }
+    }
+
+    /// Fewer retries make sense when there's another endpoint to fail over to
+    pub fn with_max_retries(mut self, max_retries: u32) -> Rpc {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Raises (or lowers) the concurrency cap from the default of one call per core
+    pub fn with_max_in_flight(mut self, in_flight: usize) -> Rpc {
+        self.concurrency = AdaptiveConcurrency::new(in_flight);
+        self
+    }
+
+    pub fn client(&self) -> &RpcClient {
+        &self.client
+    }
+
+    pub fn async_client(&self) -> &AsyncRpcClient {
+        &self.async_client
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.client.commitment()
+    }
+
+    pub fn concurrency(&self) -> &AdaptiveConcurrency {
+        &self.concurrency
+    }
+
+    pub fn call<T, F>(&self, f: F) -> ClientResult<T>
+    where
+        F: Fn(&RpcClient) -> ClientResult<T>,
+    {
+        let mut attempt = 0;
+        loop {
+            self.concurrency.acquire();
+            self.limiter.wait();
+            let result = f(&self.client);
+            self.concurrency.release();
+
+            match self.retry_delay(result, attempt)? {
+                Ok(value) => return Ok(value),
+                Err(delay) => thread::sleep(delay),
+            }
+            attempt += 1;
+        }
+    }
+
+    /// `call` for the nonblocking client. Waiting on the limiter, a free slot or a backoff
+    /// doesn't hold the thread
+    pub async fn call_async<'a, T, F, Fut>(&'a self, f: F) -> ClientResult<T>
+    where
+        F: Fn(&'a AsyncRpcClient) -> Fut,
+        Fut: Future<Output = ClientResult<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let slot = self.concurrency.acquire_async().await;
+            self.limiter.wait_async().await;
+            let result = f(&self.async_client).await;
+            drop(slot);
+
+            match self.retry_delay(result, attempt)? {
+                Ok(value) => return Ok(value),
+                Err(delay) => tokio::time::sleep(delay).await,
+            }
+            attempt += 1;
+        }
+    }
+
+    /// The value of a call that worked, or how long to wait before trying a failed one again.
+    /// Errors that aren't worth retrying, or are out of retries, come back as they are
+    fn retry_delay<T>(
+        &self,
+        result: ClientResult<T>,
+        attempt: u32,
+    ) -> ClientResult<std::result::Result<T, Duration>> {
+        let error = match result {
+            Ok(value) => {
+                self.concurrency.on_success();
+                return Ok(Ok(value));
+            }
+            Err(e) => e,
+        };
+
+        let kind = classify_error(&error);
+        if !kind.is_retryable() || attempt >= self.max_retries {
+            return Err(error);
+        }
+        if kind == RpcErrorKind::RateLimited {
+            self.concurrency.on_throttled();
+        }
+        let delay = backoff_delay(attempt);
+        debug!(
+            "{:?} from {}, retrying in {}ms: {}",
+            kind,
+            self.url,
+            delay.as_millis(),
+            error
+        );
+        Ok(Err(delay))
+    }
+
+    pub fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account> {
+        self.call(|client| client.get_account(pubkey))
+    }
+
+    pub async fn get_account_with_commitment_async(
+        &self,
+        pubkey: &Pubkey,
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Response<Option<Account>>> {
+        self.call_async(|client| client.get_account_with_commitment(pubkey, commitment))
+            .await
+    }
+
+    pub async fn get_multiple_accounts_with_commitment_async(
+        &self,
+        pubkeys: &[Pubkey],
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Response<Vec<Option<Account>>>> {
+        self.call_async(|client| client.get_multiple_accounts_with_commitment(pubkeys, commitment))
+            .await
+    }
+
+    pub async fn get_program_accounts_with_config_async(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> ClientResult<Vec<(Pubkey, Account)>> {
+        self.call_async(|client| {
+            client.get_program_accounts_with_config(program_id, config.clone())
+        })
+        .await
+    }
+
+    pub fn get_account_with_commitment(
+        &self,
+        pubkey: &Pubkey,
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Response<Option<Account>>> {
+        self.call(|client| client.get_account_with_commitment(pubkey, commitment))
+    }
+
+    pub fn get_multiple_accounts_with_commitment(
+        &self,
+        pubkeys: &[Pubkey],
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Response<Vec<Option<Account>>>> {
+        self.call(|client| client.get_multiple_accounts_with_commitment(pubkeys, commitment))
+    }
+
+    pub fn get_slot_with_commitment(&self, commitment: CommitmentConfig) -> ClientResult<Slot> {
+        self.call(|client| client.get_slot_with_commitment(commitment))
+    }
+
+    pub fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> ClientResult<Vec<(Pubkey, Account)>> {
+        self.call(|client| client.get_program_accounts_with_config(program_id, config.clone()))
+    }
+
+    pub fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        self.call(|client| client.get_latest_blockhash())
+    }
+
+    pub fn get_latest_blockhash_with_commitment(
+        &self,
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Hash> {
+        self.call(|client| Ok(client.get_latest_blockhash_with_commitment(commitment)?.0))
+    }
+
+    /// The latest blockhash, and the last block height a transaction using it can land in
+    pub async fn get_latest_blockhash_and_height(
+        &self,
+        commitment: CommitmentConfig,
+    ) -> ClientResult<(Hash, u64)> {
+        self.call_async(|client| client.get_latest_blockhash_with_commitment(commitment))
+            .await
+    }
+
+    pub fn send_and_confirm_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        self.send_and_confirm_transaction_with_commitment(transaction, self.commitment())
+    }
+
+    /// Like `send_and_confirm_until`, for when the blockhash's last valid height isn't known.
+    /// A blockhash only lasts `MAX_PROCESSING_AGE` blocks, so the transaction can't land after
+    /// that many blocks from now
+    pub fn send_and_confirm_transaction_with_commitment(
+        &self,
+        transaction: &Transaction,
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Signature> {
+        block_on(async {
+            let block_height = self.get_block_height().await?;
+            self.send_and_confirm_until(
+                transaction,
+                block_height + MAX_PROCESSING_AGE as u64,
+                commitment,
+            )
+            .await
+        })
+    }
+
+    /// Sends the transaction, preflighting at `commitment`, and waits for it to reach
+    /// `commitment`. It's sent again on every poll in case a leader dropped it, until it lands
+    /// or the chain passes `last_valid_block_height`. Every send and poll is its own call, so
+    /// waiting doesn't hold a concurrency slot or skip the rate limiter
+    pub async fn send_and_confirm_until(
+        &self,
+        transaction: &Transaction,
+        last_valid_block_height: u64,
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Signature> {
+        let config = RpcSendTransactionConfig {
+            preflight_commitment: Some(commitment.commitment),
+            ..RpcSendTransactionConfig::default()
+        };
+        let signature = self
+            .call_async(|client| client.send_transaction_with_config(transaction, config))
+            .await?;
+        // It already passed preflight, and a resend of one that landed would fail it
+        let resend_config = RpcSendTransactionConfig {
+            skip_preflight: true,
+            ..config
+        };
+
+        loop {
+            let status = self
+                .call_async(|client| {
+                    client.get_signature_status_with_commitment(&signature, commitment)
+                })
+                .await?;
+            match status {
+                Some(Ok(_)) => return Ok(signature),
+                Some(Err(e)) => return Err(e.into()),
+                None => {
+                    if self.get_block_height().await? > last_valid_block_height {
+                        return Err(RpcError::ForUser(format!(
+                            "Unable to confirm transaction {}, the blockhash expired",
+                            signature
+                        ))
+                        .into());
+                    }
+                    if let Err(e) = self
+                        .call_async(|client| {
+                            client.send_transaction_with_config(transaction, resend_config)
+                        })
+                        .await
+                    {
+                        debug!("Resending {} to {} failed: {}", signature, self.url, e);
+                    }
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+            }
+        }
+    }
+
+    async fn get_block_height(&self) -> ClientResult<u64> {
+        self.call_async(|client| {
+            client.get_block_height_with_commitment(CommitmentConfig::processed())
+        })
+        .await
+    }
+}
+
+/// Runs async rpc calls from blocking code, on the shared runtime
+fn block_on<T>(future: impl Future<Output = ClientResult<T>>) -> ClientResult<T> {
+    runtime()
+        .map_err(|e| ClientError::from(ClientErrorKind::Custom(e.report())))?
+        .block_on(future)
+}
+
+/// An endpoint as given on the command line: `URL[,weight=N][,rps=N][,heavy]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointConfig {
+    pub url: String,
+    pub weight: u32,
+    pub rps: Option<NonZeroU32>,
+    pub heavy: bool,
+}
+
+impl EndpointConfig {
+    pub fn new(url: String) -> EndpointConfig {
+        EndpointConfig {
+            url,
+            weight: 1,
+            rps: None,
+            heavy: false,
+        }
+    }
+}
+
+impl FromStr for EndpointConfig {
+    type Err = MetabobError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bad_number =
+            |e| MetabobError::config_with_source(format!("Bad number in endpoint {}", s), e);
+        let mut parts = s.split(',').map(str::trim);
+        let url = parts
+            .next()
+            .filter(|url| !url.is_empty())
+            .ok_or_else(|| MetabobError::config(format!("Endpoint is missing a url: {}", s)))?;
+        let mut endpoint = EndpointConfig::new(url.to_string());
+        for part in parts {
+            match part.split_once('=') {
+                Some(("weight", weight)) => endpoint.weight = weight.parse().map_err(bad_number)?,
+                Some(("rps", rps)) => endpoint.rps = Some(parse_rps(rps)?),
+                None if part == "heavy" => endpoint.heavy = true,
+                _ => {
+                    return Err(MetabobError::config(format!(
+                        "Unknown endpoint option {} in {}",
+                        part, s
+                    )))
+                }
+            }
+        }
+        Ok(endpoint)
+    }
+}
+
+pub struct Endpoint {
+    pub rpc: Rpc,
+    pub weight: u32,
+    pub heavy: bool,
+}
+
+/// Spreads calls over several endpoints. Reads go round robin in proportion to each
+/// endpoint's weight, heavy calls (getProgramAccounts) only go to endpoints marked heavy,
+/// and a call that keeps failing on one endpoint moves on to the next. Clones share the
+/// same endpoints, so one can be handed to each in-flight task
+#[derive(Clone)]
+pub struct RpcPool {
+    endpoints: Arc<Vec<Endpoint>>,
+    reads: Vec<usize>,
+    heavy: Vec<usize>,
+    next_read: Arc<AtomicUsize>,
+    next_heavy: Arc<AtomicUsize>,
+    in_flight: usize,
+}
+
+impl RpcPool {
+    pub fn new(endpoints: Vec<Endpoint>) -> Result<RpcPool> {
+        let reads: Vec<usize> = endpoints
+            .iter()
+            .enumerate()
+            .flat_map(|(i, endpoint)| (0..endpoint.weight).map(move |_| i))
+            .collect();
+        let heavy: Vec<usize> = endpoints
+            .iter()
+            .enumerate()
+            .filter(|(_, endpoint)| endpoint.heavy)
+            .map(|(i, _)| i)
+            .collect();
+        if reads.is_empty() {
+            return Err(MetabobError::config(
+                "At least one RPC endpoint needs a weight above 0",
+            ));
+        }
+        if heavy.is_empty() {
+            return Err(MetabobError::config(
+                "At least one RPC endpoint needs to be marked heavy",
+            ));
+        }
+
+        let in_flight = endpoints
+            .iter()
+            .filter(|endpoint| endpoint.weight > 0)
+            .map(|endpoint| endpoint.rpc.concurrency().max())
+            .sum();
+        Ok(RpcPool {
+            endpoints: Arc::new(endpoints),
+            reads,
+            heavy,
+            next_read: Arc::new(AtomicUsize::new(0)),
+            next_heavy: Arc::new(AtomicUsize::new(0)),
+            in_flight,
+        })
+    }
+
+    /// The main rpc (and the heavy one, if given) share the global limiter. Extra endpoints each
+    /// get their own. Without any endpoint marked heavy, the main rpc handles the heavy calls.
+    /// Each endpoint allows up to `in_flight` calls at once
+    pub fn build(
+        rpc: String,
+        heavy_rpc: Option<String>,
+        extra_endpoints: &[EndpointConfig],
+        timeout: Duration,
+        commitment: CommitmentConfig,
+        in_flight: usize,
+    ) -> Result<RpcPool> {
+        let limiter = RateLimiter::new();
+        let has_heavy = heavy_rpc.is_some() || extra_endpoints.iter().any(|e| e.heavy);
+        let mut endpoints = vec![Endpoint {
+            rpc: Rpc::new(rpc, timeout, commitment, limiter.clone()),
+            weight: 1,
+            heavy: !has_heavy,
+        }];
+        if let Some(heavy_rpc) = heavy_rpc {
+            endpoints.push(Endpoint {
+                rpc: Rpc::new(heavy_rpc, timeout, commitment, limiter),
+                weight: 0,
+                heavy: true,
+            });
+        }
+        for endpoint in extra_endpoints {
+            let limiter = RateLimiter::for_endpoint(&endpoint.url, endpoint.rps);
+            endpoints.push(Endpoint {
+                rpc: Rpc::new(endpoint.url.clone(), timeout, commitment, limiter),
+                weight: endpoint.weight,
+                heavy: endpoint.heavy,
+            });
+        }
+
+        endpoints = endpoints
+            .into_iter()
+            .map(|endpoint| Endpoint {
+                rpc: endpoint.rpc.with_max_in_flight(in_flight),
+                ..endpoint
+            })
+            .collect();
+
+        // Don't sit on a struggling endpoint for long when there's another one to try
+        if endpoints.len() > 1 {
+            endpoints = endpoints
+                .into_iter()
+                .map(|endpoint| Endpoint {
+                    rpc: endpoint.rpc.with_max_retries(RPC_POOL_MAX_RETRIES),
+                    ..endpoint
+                })
+                .collect();
+        }
+        RpcPool::new(endpoints)
+    }
+
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    /// The first endpoint, which is the one from `--rpc` or the Solana config
+    pub fn primary(&self) -> &Rpc {
+        &self.endpoints[0].rpc
+    }
+
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.primary().commitment()
+    }
+
+    /// How many calls the endpoints can have going at once between them
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// The next read endpoint, for calls that all have to see the same node, like getting a
+    /// blockhash and then sending and confirming a transaction with it
+    pub fn pin(&self) -> &Rpc {
+        let i = self.reads[self.next_read.fetch_add(1, Ordering::Relaxed) % self.reads.len()];
+        &self.endpoints[i].rpc
+    }
+
+    fn call_on<T, F>(&self, schedule: &[usize], next: &AtomicUsize, f: F) -> ClientResult<T>
+    where
+        F: Fn(&RpcClient) -> ClientResult<T>,
+    {
+        let first = schedule[next.fetch_add(1, Ordering::Relaxed) % schedule.len()];
+        let mut order = vec![first];
+        for i in schedule {
+            if !order.contains(i) {
+                order.push(*i);
+            }
+        }
+
+        let mut last_error = None;
+        for i in order {
+            let rpc = &self.endpoints[i].rpc;
+            match rpc.call(&f) {
+                Ok(value) => return Ok(value),
+                Err(e) if classify_error(&e).is_retryable() => {
+                    warn!("{} failed, trying the next endpoint: {}", rpc.url(), e);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error.unwrap())
+    }
+
+    pub fn call<T, F>(&self, f: F) -> ClientResult<T>
+    where
+        F: Fn(&RpcClient) -> ClientResult<T>,
+    {
+        self.call_on(&self.reads, &self.next_read, f)
+    }
+
+    /// `call_on` for the nonblocking client
+    async fn call_on_async<'a, T, F, Fut>(
+        &'a self,
+        schedule: &[usize],
+        next: &AtomicUsize,
+        f: F,
+    ) -> ClientResult<T>
+    where
+        F: Fn(&'a AsyncRpcClient) -> Fut,
+        Fut: Future<Output = ClientResult<T>>,
+    {
+        let first = schedule[next.fetch_add(1, Ordering::Relaxed) % schedule.len()];
+        let mut order = vec![first];
+        for i in schedule {
+            if !order.contains(i) {
+                order.push(*i);
+            }
+        }
+
+        let mut last_error = None;
+        for i in order {
+            let rpc = &self.endpoints[i].rpc;
+            match rpc.call_async(&f).await {
+                Ok(value) => return Ok(value),
+                Err(e) if classify_error(&e).is_retryable() => {
+                    warn!("{} failed, trying the next endpoint: {}", rpc.url(), e);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error.unwrap())
+    }
+
+    pub async fn call_async<'a, T, F, Fut>(&'a self, f: F) -> ClientResult<T>
+    where
+        F: Fn(&'a AsyncRpcClient) -> Fut,
+        Fut: Future<Output = ClientResult<T>>,
+    {
+        self.call_on_async(&self.reads, &self.next_read, f).await
+    }
+
+    pub async fn heavy_call_async<'a, T, F, Fut>(&'a self, f: F) -> ClientResult<T>
+    where
+        F: Fn(&'a AsyncRpcClient) -> Fut,
+        Fut: Future<Output = ClientResult<T>>,
+    {
+        self.call_on_async(&self.heavy, &self.next_heavy, f).await
+    }
+
+    pub fn heavy_call<T, F>(&self, f: F) -> ClientResult<T>
+    where
+        F: Fn(&RpcClient) -> ClientResult<T>,
+    {
+        self.call_on(&self.heavy, &self.next_heavy, f)
+    }
+
+    pub fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account> {
+        self.call(|client| client.get_account(pubkey))
+    }
+
+    pub async fn get_account_with_commitment_async(
+        &self,
+        pubkey: &Pubkey,
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Response<Option<Account>>> {
+        self.call_async(|client| client.get_account_with_commitment(pubkey, commitment))
+            .await
+    }
+
+    pub async fn get_multiple_accounts_with_commitment_async(
+        &self,
+        pubkeys: &[Pubkey],
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Response<Vec<Option<Account>>>> {
+        self.call_async(|client| client.get_multiple_accounts_with_commitment(pubkeys, commitment))
+            .await
+    }
+
+    pub async fn get_program_accounts_with_config_async(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> ClientResult<Vec<(Pubkey, Account)>> {
+        self.heavy_call_async(|client| {
+            client.get_program_accounts_with_config(program_id, config.clone())
+        })
+        .await
+    }
+
+    pub fn get_account_with_commitment(
+        &self,
+        pubkey: &Pubkey,
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Response<Option<Account>>> {
+        self.call(|client| client.get_account_with_commitment(pubkey, commitment))
+    }
+
+    pub fn get_multiple_accounts_with_commitment(
+        &self,
+        pubkeys: &[Pubkey],
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Response<Vec<Option<Account>>>> {
+        self.call(|client| client.get_multiple_accounts_with_commitment(pubkeys, commitment))
+    }
+
+    pub fn get_slot_with_commitment(&self, commitment: CommitmentConfig) -> ClientResult<Slot> {
+        self.call(|client| client.get_slot_with_commitment(commitment))
+    }
+
+    pub fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> ClientResult<Vec<(Pubkey, Account)>> {
+        self.heavy_call(|client| {
+            client.get_program_accounts_with_config(program_id, config.clone())
+        })
+    }
+
+    pub fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        self.call(|client| client.get_latest_blockhash())
+    }
+
+    pub fn get_latest_blockhash_with_commitment(
+        &self,
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Hash> {
+        self.call(|client| Ok(client.get_latest_blockhash_with_commitment(commitment)?.0))
+    }
+
+    /// Sends and confirms on one endpoint, so a lagging node can't call it expired early
+    pub fn send_and_confirm_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        self.pin().send_and_confirm_transaction(transaction)
+    }
+
+    pub fn send_and_confirm_transaction_with_commitment(
+        &self,
+        transaction: &Transaction,
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Signature> {
+        self.pin()
+            .send_and_confirm_transaction_with_commitment(transaction, commitment)
+    }
+}
@@ -0,0 +1,97 @@
+use crate::errors::{MetabobError, Result};
+use futures::{
+    pin_mut,
+    stream::{self, StreamExt},
+    Future,
+};
+use std::{panic, sync::Arc, sync::OnceLock};
+use tokio::{
+    runtime::{Builder, Runtime},
+    task,
+};
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// The runtime every nonblocking rpc call runs on. A client's pooled connections belong to the
+/// runtime that opened them, so there's only ever the one
+pub fn runtime() -> Result<&'static Runtime> {
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime);
+    }
+    let runtime = Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| MetabobError::config_with_source("Couldn't start the async runtime", e))?;
+    Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+/// Runs `call` on every item with up to `in_flight` of the futures it returns going at once,
+/// handing each result to `handle` on the calling thread as it comes back. Meant for the
+/// nonblocking rpc client, so nothing holds a thread while it waits on the rpc. Items are
+/// pulled lazily, and the first error from `handle` stops the run
+pub fn for_each_in_flight<I, T, R, F, Fut, H>(
+    items: I,
+    in_flight: usize,
+    call: F,
+    mut handle: H,
+) -> Result<()>
+where
+    I: IntoIterator<Item = T>,
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = R>,
+    H: FnMut(R) -> Result<()>,
+{
+    let in_flight = in_flight.max(1);
+    runtime()?.block_on(async {
+        let results = stream::iter(items).map(call).buffer_unordered(in_flight);
+        pin_mut!(results);
+        while let Some(result) = results.next().await {
+            handle(result)?;
+        }
+        Ok(())
+    })
+}
+
+/// Same as `for_each_in_flight`, for calls that can only block, like the blocking HTTP client.
+/// Each one gets a thread from tokio's blocking pool, which is sized to `in_flight` rather than
+/// to the number of cores
+pub fn for_each_blocking_in_flight<I, T, R, F, H>(
+    items: I,
+    in_flight: usize,
+    call: F,
+    mut handle: H,
+) -> Result<()>
+where
+    I: IntoIterator<Item = T>,
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> R + Send + Sync + 'static,
+    H: FnMut(R) -> Result<()>,
+{
+    let in_flight = in_flight.max(1);
+    let runtime = Builder::new_multi_thread()
+        .worker_threads(1)
+        .max_blocking_threads(in_flight)
+        .enable_all()
+        .build()
+        .map_err(|e| MetabobError::config_with_source("Couldn't start the async runtime", e))?;
+
+    let call = Arc::new(call);
+    runtime.block_on(async {
+        let results = stream::iter(items)
+            .map(|item| {
+                let call = call.clone();
+                task::spawn_blocking(move || call(item))
+            })
+            .buffer_unordered(in_flight);
+        pin_mut!(results);
+        while let Some(result) = results.next().await {
+            match result {
+                Ok(result) => handle(result)?,
+                // Nothing cancels these, so the only way to get here is a panic in `call`
+                Err(e) => panic::resume_unwind(e.into_panic()),
+            }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,146 @@
+use crate::errors::{MetabobError, Result};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Somewhere to put the files a command produces. Names are relative paths like
+/// `mint_info.json` or `draft/tokenlist.json`
+pub trait OutputSink: Sync {
+    fn create(&self, name: &str) -> Result<Box<dyn Write + Send + '_>>;
+}
+
+/// Writes files under a directory, making subdirectories as needed
+#[derive(Debug, Clone)]
+pub struct DirSink {
+    dir: PathBuf,
+    overwrite: bool,
+}
+
+impl DirSink {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> DirSink {
+        DirSink {
+            dir: dir.into(),
+            overwrite: true,
+        }
+    }
+
+    /// Fails instead of replacing files that already exist
+    pub fn no_overwrite(mut self) -> DirSink {
+        self.overwrite = false;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+}
+
+impl OutputSink for DirSink {
+    fn create(&self, name: &str) -> Result<Box<dyn Write + Send + '_>> {
+        let path = self.path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(MetabobError::io(parent))?;
+        }
+        let mut options = OpenOptions::new();
+        options.write(true);
+        if self.overwrite {
+            options.create(true).truncate(true);
+        } else {
+            options.create_new(true);
+        }
+        let file = options.open(&path).map_err(MetabobError::io(&path))?;
+        Ok(Box::new(BufWriter::new(file)))
+    }
+}
+
+/// Throws everything away, for --no-save
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullSink;
+
+impl OutputSink for NullSink {
+    fn create(&self, _name: &str) -> Result<Box<dyn Write + Send + '_>> {
+        Ok(Box::new(io::sink()))
+    }
+}
+
+/// Keeps files in memory, for callers that want the output without touching the disk
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    files: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Vec<u8>> {
+        self.files.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn into_files(self) -> BTreeMap<String, Vec<u8>> {
+        self.files.into_inner().unwrap()
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn create(&self, name: &str) -> Result<Box<dyn Write + Send + '_>> {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), Vec::new());
+        Ok(Box::new(MemoryWriter {
+            sink: self,
+            name: name.to_string(),
+        }))
+    }
+}
+
+struct MemoryWriter<'a> {
+    sink: &'a MemorySink,
+    name: String,
+}
+
+impl Write for MemoryWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut files = self.sink.files.lock().unwrap();
+        files
+            .entry(self.name.clone())
+            .or_default()
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn save_json<T: Serialize + ?Sized>(
+    sink: &dyn OutputSink,
+    name: &str,
+    value: &T,
+) -> Result<()> {
+    let mut writer = sink.create(name)?;
+    serde_json::to_writer(&mut writer, value).map_err(MetabobError::json(name))?;
+    writer.flush().map_err(MetabobError::io(name))
+}
+
+pub fn save_json_pretty<T: Serialize + ?Sized>(
+    sink: &dyn OutputSink,
+    name: &str,
+    value: &T,
+) -> Result<()> {
+    let mut writer = sink.create(name)?;
+    serde_json::to_writer_pretty(&mut writer, value).map_err(MetabobError::json(name))?;
+    writer.flush().map_err(MetabobError::io(name))
+}
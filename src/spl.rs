@@ -1,44 +1,75 @@
-use anyhow::Result;
-use indicatif::ParallelProgressIterator;
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 use solana_account_decoder::UiAccountEncoding;
 use solana_client::{
-    rpc_client::RpcClient,
     rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
-    rpc_filter::RpcFilterType,
+    rpc_filter::{Memcmp, RpcFilterType},
 };
+use solana_program::program_pack::Pack;
 use spl_token::state::Mint;
 use std::{
-    fs::{self, OpenOptions},
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Write,
     time::Instant,
 };
-use std::{
-    io::BufReader,
-    sync::{Arc, Mutex},
-};
 
-use crate::decode::{decode_metadata_account, decode_mint_account, get_metadata_pda};
-use metaplex_token_metadata::state::Metadata;
+use crate::constants::{
+    ACCOUNT_INFO_FILE, METADATA_BATCH_SIZE, MINT_INFO_FILE, MINT_SHARD_OFFSET,
+    NO_URI_TOKEN_LIST_FILE, TOKEN_LIST_FILE, URI_TOKEN_LIST_FILE,
+};
+use crate::db::{transaction, Store};
+use crate::decode::{decode_metadata_account, decode_mint_account, Metadata};
+use crate::errors::{DecodeError, Result};
+use crate::observer::Observer;
+use crate::pda::get_metadata_pda;
+use crate::rpc::RpcPool;
+use crate::runtime::for_each_in_flight;
+use crate::sink::OutputSink;
+use crate::status;
+use crate::stream::{JsonArrayWriter, NdjsonWriter};
+use crate::token_list::{diff_entries, TokenListChange, TokenListDiff};
+use log::{debug, warn};
+use serde_json::{json, Value as JsonValue};
 use spl_token::ID as TOKEN_PROGRAM_ID;
 
-use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
-use solana_sdk::{account::Account, pubkey::Pubkey};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::{account::Account, clock::Slot, pubkey::Pubkey};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MintStruct {
     pub supply: u64,
     pub decimals: u8,
+    /// None once minting is disabled, and in files saved before it was kept
+    #[serde(default)]
+    pub mint_authority: Option<Pubkey>,
+    /// None if accounts can't be frozen, and in files saved before it was kept
+    #[serde(default)]
+    pub freeze_authority: Option<Pubkey>,
+    /// Only initialized mints decode, so files saved before it was kept can assume it
+    #[serde(default = "initialized")]
+    pub is_initialized: bool,
+    /// Whether the authorities were read from chain. False for files and databases saved
+    /// before they were kept, where a None authority doesn't mean it's disabled
+    #[serde(default)]
+    pub has_authorities: bool,
 }
 impl From<Mint> for MintStruct {
     fn from(mint: Mint) -> Self {
         MintStruct {
             supply: mint.supply,
             decimals: mint.decimals,
+            mint_authority: mint.mint_authority.into(),
+            freeze_authority: mint.freeze_authority.into(),
+            is_initialized: mint.is_initialized,
+            has_authorities: true,
         }
     }
 }
 
+fn initialized() -> bool {
+    true
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MetadataStruct {
     pub mint: Pubkey,
@@ -51,17 +82,20 @@ impl From<Metadata> for MetadataStruct {
     fn from(metadata: Metadata) -> Self {
         MetadataStruct {
             mint: metadata.mint,
-            name: metadata.data.name,
-            symbol: metadata.data.symbol,
-            uri: metadata.data.uri,
+            name: metadata.name,
+            symbol: metadata.symbol,
+            uri: metadata.uri,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MintInfo {
-    mint_pubkey: Pubkey,
-    mint: MintStruct,
+    pub mint_pubkey: Pubkey,
+    pub mint: MintStruct,
+    /// Slot the mint was seen at. 0 in files saved before slots were kept
+    #[serde(default)]
+    pub slot: Slot,
 }
 impl MintInfo {
     fn is_fungible(&self) -> bool {
@@ -70,48 +104,47 @@ impl MintInfo {
     }
 }
 
-impl TryFrom<(Pubkey, Account)> for MintInfo {
-    type Error = &'static str;
-    fn try_from(tuple: (Pubkey, Account)) -> Result<Self, Self::Error> {
-        let mint = decode_mint_account(&tuple.1);
-        match mint {
-            Ok(m) => Ok(MintInfo {
-                mint_pubkey: tuple.0,
-                mint: m.into(),
-            }),
-            Err(_) => Err("Error decoding mint account"),
-        }
+impl TryFrom<(Pubkey, Account, Slot)> for MintInfo {
+    type Error = DecodeError;
+    fn try_from(tuple: (Pubkey, Account, Slot)) -> Result<Self, Self::Error> {
+        let mint = decode_mint_account(&tuple.1)?;
+        Ok(MintInfo {
+            mint_pubkey: tuple.0,
+            mint: mint.into(),
+            slot: tuple.2,
+        })
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MetadataInfo {
-    metadata_pubkey: Pubkey,
-    metadata: MetadataStruct,
+    pub metadata_pubkey: Pubkey,
+    pub metadata: MetadataStruct,
+    /// Slot the metadata was fetched at. 0 in files saved before slots were kept
+    #[serde(default)]
+    pub slot: Slot,
 }
 
-impl TryFrom<(Pubkey, Account)> for MetadataInfo {
-    type Error = &'static str;
+impl TryFrom<(Pubkey, Account, Slot)> for MetadataInfo {
+    type Error = DecodeError;
 
-    fn try_from(tuple: (Pubkey, Account)) -> Result<Self, Self::Error> {
-        let metadata = decode_metadata_account(&tuple.1);
-        match metadata {
-            Ok(m) => Ok(MetadataInfo {
-                metadata_pubkey: tuple.0,
-                metadata: m.into(),
-            }),
-            Err(_) => Err("error decoding metadata account"),
-        }
+    fn try_from(tuple: (Pubkey, Account, Slot)) -> Result<Self, Self::Error> {
+        let metadata = decode_metadata_account(&tuple.1)?;
+        Ok(MetadataInfo {
+            metadata_pubkey: tuple.0,
+            metadata: metadata.into(),
+            slot: tuple.2,
+        })
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AccountStruct {
-    mint: MintInfo,
-    metadata: MetadataInfo,
+    pub mint: MintInfo,
+    pub metadata: MetadataInfo,
 }
 impl AccountStruct {
-    fn new(mint: MintInfo, metadata: MetadataInfo) -> AccountStruct {
+    pub fn new(mint: MintInfo, metadata: MetadataInfo) -> AccountStruct {
         AccountStruct { mint, metadata }
     }
 }
@@ -119,12 +152,62 @@ impl AccountStruct {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TokenListEntry {
-    chain_id: u8,
-    address: String,
-    symbol: String,
-    name: String,
-    decimals: u8,
-    logo_uri: String,
+    pub chain_id: u8,
+    pub address: String,
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u8,
+    /// Spelled logoURI like in the token list standard. Files from older versions spelled it
+    /// logoUri, and hand curated lists often leave it out, so it's left out when empty
+    #[serde(
+        default,
+        rename = "logoURI",
+        alias = "logoUri",
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub logo_uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<TokenExtensions>,
+    /// Everything else, e.g. the tags of curated lists
+    #[serde(flatten)]
+    pub other: BTreeMap<String, JsonValue>,
+}
+
+/// The token list `extensions` object. Lists generated from chain data fill in the mint's
+/// supply and authorities. An authority left out of an entry that has a supply is disabled
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenExtensions {
+    /// A string, since supplies go past what JSON numbers can hold exactly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supply: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mint_authority: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub freeze_authority: Option<String>,
+    /// Everything else, e.g. the website and coingeckoId of curated lists
+    #[serde(flatten)]
+    pub other: BTreeMap<String, JsonValue>,
+}
+
+impl TokenExtensions {
+    /// Takes the supply and authorities from `chain`, keeping everything else
+    pub fn update_mint(&mut self, chain: &TokenExtensions) {
+        self.supply = chain.supply.clone();
+        self.mint_authority = chain.mint_authority.clone();
+        self.freeze_authority = chain.freeze_authority.clone();
+    }
+}
+
+impl From<&MintStruct> for TokenExtensions {
+    fn from(mint: &MintStruct) -> Self {
+        TokenExtensions {
+            supply: Some(mint.supply.to_string()),
+            mint_authority: mint.mint_authority.map(|key| key.to_string()),
+            freeze_authority: mint.freeze_authority.map(|key| key.to_string()),
+            other: BTreeMap::new(),
+        }
+    }
 }
 
 impl TokenListEntry {
@@ -146,6 +229,8 @@ impl TokenListEntry {
             name,
             decimals,
             logo_uri,
+            extensions: None,
+            other: BTreeMap::new(),
         }
     }
 }
@@ -154,289 +239,624 @@ impl From<AccountStruct> for TokenListEntry {
     fn from(a: AccountStruct) -> Self {
         let mint = a.mint;
         let metadata = a.metadata.metadata;
-        TokenListEntry::new(
-            mint.mint_pubkey.to_string(),
-            metadata.symbol,
-            metadata.name,
-            mint.mint.decimals,
-            metadata.uri,
-        )
+        TokenListEntry {
+            extensions: Some(TokenExtensions::from(&mint.mint)),
+            ..TokenListEntry::new(
+                mint.mint_pubkey.to_string(),
+                metadata.symbol,
+                metadata.name,
+                mint.mint.decimals,
+                metadata.uri,
+            )
+        }
     }
 }
 
+/// How the metadata scan went. The accounts themselves are handed over as they're found
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MetadataScan {
+    pub found: usize,
+    pub failed_mints: Vec<Pubkey>,
+}
+
+/// How many token list entries did and didn't have a logo uri
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct TokenListCounts {
+    pub with_uri: usize,
+    pub without_uri: usize,
+}
+
+/// The end result of the whole pipeline
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TokenListRun {
+    pub mints: usize,
+    pub metadata: MetadataScan,
+    pub token_list: TokenListCounts,
+}
+
+/// What an earlier run found, for `refresh` to compare against. Accounts are keyed by mint
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub mints: HashSet<Pubkey>,
+    pub accounts: HashMap<Pubkey, AccountStruct>,
+    pub failed_mints: HashSet<Pubkey>,
+}
+
+impl Snapshot {
+    pub fn load<M, A, F>(mints: M, accounts: A, failed_mints: F) -> Result<Snapshot>
+    where
+        M: IntoIterator<Item = Result<MintInfo>>,
+        A: IntoIterator<Item = Result<AccountStruct>>,
+        F: IntoIterator<Item = Pubkey>,
+    {
+        let mut snapshot = Snapshot {
+            failed_mints: failed_mints.into_iter().collect(),
+            ..Snapshot::default()
+        };
+        for mint in mints {
+            snapshot.mints.insert(mint?.mint_pubkey);
+        }
+        for account in accounts {
+            let account = account?;
+            snapshot.accounts.insert(account.mint.mint_pubkey, account);
+        }
+        Ok(snapshot)
+    }
+}
+
+/// The end result of a refresh
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RefreshRun {
+    pub mints: usize,
+    pub new_mints: usize,
+    pub rechecked: usize,
+    /// Known mints that had no metadata last time, checked again in case it's been made since
+    pub without_metadata: usize,
+    pub metadata: MetadataScan,
+    pub token_list: TokenListCounts,
+    pub diff: TokenListDiff,
+}
+
+/// Runs the whole token list pipeline. Every record streams straight through to its files, so
+/// only the mint list is ever held in memory. Intermediate NDJSON files go to `intermediates`
+/// and the final uri/no uri token lists to `output`. With a `store`, every record is upserted
+/// into it too
 pub fn do_everything(
-    client: &RpcClient,
-    heavy_client: &RpcClient,
-    no_save: bool,
-) -> Result<Vec<TokenListEntry>> {
-    let fungible_mint_accounts = get_mint_accounts(heavy_client, no_save)?;
-    let account_info = get_metadata_accounts(client, Some(fungible_mint_accounts), no_save)?;
-    let token_list = get_token_entries(Some(account_info), no_save)?;
-    parse_token_uri(Some(token_list), false)
-}
-
-pub fn get_mint_accounts(client: &RpcClient, no_save: bool) -> Result<Vec<MintInfo>> {
-    let mut mint_accounts_file = OpenOptions::new()
-        .write(true)
-        .read(true)
-        .create(true)
-        .open("./mint_info.json")?;
-    let filter1 = RpcFilterType::DataSize(82);
-    let commitment = CommitmentConfig {
-        commitment: CommitmentLevel::Finalized,
-    };
+    client: &RpcPool,
+    commitment: CommitmentConfig,
+    observer: &dyn Observer,
+    intermediates: &dyn OutputSink,
+    output: &dyn OutputSink,
+    store: Option<&Store>,
+) -> Result<TokenListRun> {
+    transaction(store, || {
+        let mints = scan_and_write_mints(client, commitment, observer, intermediates, store)?;
+        let mint_count = mints.len();
+        let mut writers = PipelineWriters::create(intermediates, output, store)?;
+        let metadata = get_metadata_accounts(
+            client,
+            mints.into_iter().map(Ok),
+            mint_count as u64,
+            commitment,
+            observer,
+            |account| writers.write(account).map(|_| ()),
+        )?;
+        if let Some(store) = store {
+            store.upsert_failed_mints(&metadata.failed_mints)?;
+        }
+        let token_list = writers.finish(observer, metadata.found)?;
+
+        Ok(TokenListRun {
+            mints: mint_count,
+            metadata,
+            token_list,
+        })
+    })
+}
+
+/// Brings an earlier run up to date in batches instead of looking up every mint's metadata on
+/// its own. Mints are listed fresh, then metadata is fetched 100 mints at a time for every one
+/// of them: new mints, ones that failed last time, known tokens, and mints that had no metadata
+/// last time, since it can be made at any point after the mint. Writes the same files as
+/// `do_everything` and returns how the token list changed
+pub fn refresh(
+    client: &RpcPool,
+    commitment: CommitmentConfig,
+    observer: &dyn Observer,
+    previous: Snapshot,
+    intermediates: &dyn OutputSink,
+    output: &dyn OutputSink,
+    store: Option<&Store>,
+) -> Result<RefreshRun> {
+    let Snapshot {
+        mints: known_mints,
+        accounts: mut previous_accounts,
+        failed_mints: retry_mints,
+    } = previous;
+
+    transaction(store, || {
+        let mints = scan_and_write_mints(client, commitment, observer, intermediates, store)?;
+        let mint_count = mints.len();
+        let (mut new_mints, mut rechecked, mut without_metadata) = (0, 0, 0);
+        for mint in &mints {
+            let key = &mint.mint_pubkey;
+            if previous_accounts.contains_key(key) {
+                rechecked += 1;
+            } else if !known_mints.contains(key) || retry_mints.contains(key) {
+                new_mints += 1;
+            } else {
+                without_metadata += 1;
+            }
+        }
+        status!(
+            observer,
+            "Checking metadata for {} new mints, {} known tokens and {} mints that had none...",
+            new_mints,
+            rechecked,
+            without_metadata
+        );
+        let live_mints: HashSet<Pubkey> = mints.iter().map(|mint| mint.mint_pubkey).collect();
+        let mut writers = PipelineWriters::create(intermediates, output, store)?;
+        let mut diff = TokenListDiff::default();
+        let metadata =
+            get_metadata_accounts_batched(client, mints, commitment, observer, |account| {
+                let previous = previous_accounts.remove(&account.mint.mint_pubkey);
+                let entry = writers.write(account)?;
+                match previous {
+                    Some(previous) => {
+                        let changes = diff_entries(&TokenListEntry::from(previous), &entry);
+                        if !changes.is_empty() {
+                            diff.changed.push(TokenListChange {
+                                chain_id: entry.chain_id,
+                                address: entry.address,
+                                changes,
+                            });
+                        }
+                    }
+                    None => diff.added.push(entry),
+                }
+                Ok(())
+            })?;
+        // Tokens we couldn't re-check keep what we knew about them instead of dropping out
+        for mint in &metadata.failed_mints {
+            if let Some(previous) = previous_accounts.remove(mint) {
+                writers.write(previous)?;
+            }
+        }
+        for (mint, previous) in previous_accounts {
+            if let Some(store) = store {
+                store.remove_token(&mint)?;
+            }
+            diff.removed.push(TokenListEntry::from(previous));
+        }
+        if let Some(store) = store {
+            for mint in known_mints.difference(&live_mints) {
+                store.remove_mint(mint)?;
+            }
+            store.set_failed_mints(&metadata.failed_mints)?;
+        }
+        let token_list = writers.finish(observer, metadata.found)?;
+        diff.sort();
+        report_token_list_diff(observer, &diff);
+
+        Ok(RefreshRun {
+            mints: mint_count,
+            new_mints,
+            rechecked,
+            without_metadata,
+            metadata,
+            token_list,
+            diff,
+        })
+    })
+}
+
+/// Every file the pipeline writes after the mint list, plus the store, fed one account at a time
+struct PipelineWriters<'a> {
+    accounts: NdjsonWriter<Box<dyn Write + Send + 'a>>,
+    tokens: NdjsonWriter<Box<dyn Write + Send + 'a>>,
+    uri: JsonArrayWriter<Box<dyn Write + Send + 'a>>,
+    no_uri: JsonArrayWriter<Box<dyn Write + Send + 'a>>,
+    store: Option<&'a Store>,
+}
+
+impl<'a> PipelineWriters<'a> {
+    fn create(
+        intermediates: &'a dyn OutputSink,
+        output: &'a dyn OutputSink,
+        store: Option<&'a Store>,
+    ) -> Result<PipelineWriters<'a>> {
+        Ok(PipelineWriters {
+            accounts: NdjsonWriter::new(
+                intermediates.create(ACCOUNT_INFO_FILE)?,
+                ACCOUNT_INFO_FILE,
+            ),
+            tokens: NdjsonWriter::new(intermediates.create(TOKEN_LIST_FILE)?, TOKEN_LIST_FILE),
+            uri: JsonArrayWriter::new(output.create(URI_TOKEN_LIST_FILE)?, URI_TOKEN_LIST_FILE),
+            no_uri: JsonArrayWriter::new(
+                output.create(NO_URI_TOKEN_LIST_FILE)?,
+                NO_URI_TOKEN_LIST_FILE,
+            ),
+            store,
+        })
+    }
+
+    /// Writes the account and its token list entry everywhere, handing back the entry
+    fn write(&mut self, account: AccountStruct) -> Result<TokenListEntry> {
+        self.accounts.write(&account)?;
+        if let Some(store) = self.store {
+            store.upsert_account(&account)?;
+        }
+        let entry = TokenListEntry::from(account);
+        self.tokens.write(&entry)?;
+        if let Some(store) = self.store {
+            store.upsert_token_entry(&entry)?;
+        }
+        if entry.logo_uri.is_empty() {
+            self.no_uri.write(&entry)?;
+        } else {
+            self.uri.write(&entry)?;
+        }
+        Ok(entry)
+    }
+
+    fn finish(self, observer: &dyn Observer, found: usize) -> Result<TokenListCounts> {
+        self.accounts.finish()?;
+        report_token_entries(observer, found, self.tokens.finish()?);
+        let token_list = TokenListCounts {
+            with_uri: self.uri.finish()?,
+            without_uri: self.no_uri.finish()?,
+        };
+        report_token_list(observer, token_list);
+        Ok(token_list)
+    }
+}
+
+/// Scans the mints, writing each shard to the mint file and the store as it comes in, and
+/// returns the fungible ones
+fn scan_and_write_mints(
+    client: &RpcPool,
+    commitment: CommitmentConfig,
+    observer: &dyn Observer,
+    intermediates: &dyn OutputSink,
+    store: Option<&Store>,
+) -> Result<Vec<MintInfo>> {
+    let mut mint_writer = NdjsonWriter::new(intermediates.create(MINT_INFO_FILE)?, MINT_INFO_FILE);
+    let mut mints = Vec::new();
+    scan_mint_accounts(client, commitment, observer, |shard| {
+        for mint in &shard {
+            mint_writer.write(mint)?;
+            if let Some(store) = store {
+                store.upsert_mint(mint)?;
+            }
+        }
+        mints.extend(shard);
+        Ok(())
+    })?;
+    mint_writer.finish()?;
+    Ok(mints)
+}
+
+pub fn get_mint_accounts(
+    client: &RpcPool,
+    commitment: CommitmentConfig,
+    observer: &dyn Observer,
+) -> Result<Vec<MintInfo>> {
+    let mut mints = Vec::new();
+    scan_mint_accounts(client, commitment, observer, |shard| {
+        mints.extend(shard);
+        Ok(())
+    })?;
+    Ok(mints)
+}
 
+/// Lists every mint with one getProgramAccounts call per shard (see `MINT_SHARD_OFFSET`),
+/// handing the fungible mints of each shard to `on_shard` before fetching the next, so the raw
+/// accounts of the whole scan are never in memory at once. Returns how many fungible mints
+/// there were
+pub fn scan_mint_accounts<F>(
+    client: &RpcPool,
+    commitment: CommitmentConfig,
+    observer: &dyn Observer,
+    mut on_shard: F,
+) -> Result<usize>
+where
+    F: FnMut(Vec<MintInfo>) -> Result<()>,
+{
     let account_config = RpcAccountInfoConfig {
         encoding: Some(UiAccountEncoding::Base64),
-        // data_slice: Some(UiDataSliceConfig {
-        //     length: 0,
-        //     offset: 0,
-        // }),
         data_slice: None,
         commitment: Some(commitment),
+        min_context_slot: None,
     };
 
-    let config = RpcProgramAccountsConfig {
-        filters: Some(vec![filter1]),
-        account_config,
-        with_context: None,
-    };
-
-    println!("Looking for mint accounts. This may take a while...");
+    status!(
+        observer,
+        "Looking for mint accounts. This may take a while..."
+    );
     let start = Instant::now();
-    let mint_tuple = client.get_program_accounts_with_config(&TOKEN_PROGRAM_ID, config)?;
+    // Program account scans don't say what slot they're from, so stamp the mints with the
+    // slot the scan started at
+    let slot = client.get_slot_with_commitment(commitment)?;
+    let progress = observer.progress("get_mints", 256);
+    let (mut total_mints, mut fungible_mints) = (0, 0);
+    for shard in 0..=u8::MAX {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::DataSize(Mint::LEN as u64),
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(MINT_SHARD_OFFSET, &[shard])),
+            ]),
+            account_config: account_config.clone(),
+            with_context: None,
+        };
+        let mint_tuple = client.get_program_accounts_with_config(&TOKEN_PROGRAM_ID, config)?;
+        total_mints += mint_tuple.len();
+        let mints = filter_mints(mint_tuple, slot);
+        fungible_mints += mints.len();
+        on_shard(mints)?;
+        progress.inc(1);
+    }
+    progress.finish();
     let duration = start.elapsed();
-    println!(
+    status!(
+        observer,
         "Found {} mint accounts in {} minutes and {} seconds!",
-        mint_tuple.len(),
+        total_mints,
         duration.as_secs() / 60,
         duration.as_secs() % 60
     );
-
-    println!("Filtering mint accounts...");
-    let parsed_mint_accounts = filter_mints(mint_tuple);
-    println!(
-        "Total fungible mint accounts: {}",
-        parsed_mint_accounts.len()
+    status!(observer, "Total fungible mint accounts: {}", fungible_mints);
+    observer.event(
+        "mints",
+        json!({ "total": total_mints, "fungible": fungible_mints, "slot": slot, "seconds": duration.as_secs() }),
     );
-    if !no_save {
-        println!("Saving fungible mint accounts file...");
-        let start = Instant::now();
-        serde_json::to_writer(&mut mint_accounts_file, &parsed_mint_accounts)?;
-        let duration = start.elapsed();
-        println!(
-            "Saved fungible mint accounts file in {} minutes and {} seconds!",
-            duration.as_secs() / 60,
-            duration.as_secs() % 60
-        );
-    };
-    Ok(parsed_mint_accounts)
-}
-
-pub fn get_metadata_accounts(
-    client: &RpcClient,
-    mint_info: Option<Vec<MintInfo>>,
-    no_save: bool,
-) -> Result<Vec<AccountStruct>> {
-    let mint_info = match mint_info {
-        Some(t) => t,
-        None => {
-            let token_list_file = OpenOptions::new()
-                .write(true)
-                .read(true)
-                .open("./mint_info.json")?;
-            println!("Reading fungible mint info from file...");
-            let reader = BufReader::new(&token_list_file);
-            let to_return: Vec<MintInfo> =
-                serde_json::from_reader(reader).expect("Error parsing mint info file");
-            println!("Read {} fungible mint accounts.", to_return.len());
-            to_return
-        }
-    };
+    Ok(fungible_mints)
+}
 
-    let account_info: Arc<Mutex<Vec<AccountStruct>>> = Arc::new(Mutex::new(Vec::new()));
-    let commitment = CommitmentConfig {
-        commitment: CommitmentLevel::Finalized,
-    };
-    mint_info.par_iter().progress().for_each(|mint_info| {
-        let metadata_pubkey = get_metadata_pda(&mint_info.mint_pubkey);
-        let metadata_account =
-            RpcClient::get_account_with_commitment(client, &metadata_pubkey, commitment);
-        if let Ok(account) = metadata_account {
-            let account = account.value;
-            if let Some(account) = account {
-                let account_info = account_info.clone();
-                let metadata_info = MetadataInfo::try_from((metadata_pubkey, account));
-                if let Ok(..) = metadata_info {
-                    account_info.lock().unwrap().push(AccountStruct::new(
-                        mint_info.clone(),
-                        metadata_info.unwrap(),
-                    ));
+/// Looks up the metadata PDA of every mint, handing each fungible mint that has metadata to
+/// `on_account` as the lookups come back. `total` is only used for progress
+pub fn get_metadata_accounts<I, F>(
+    client: &RpcPool,
+    mints: I,
+    total: u64,
+    commitment: CommitmentConfig,
+    observer: &dyn Observer,
+    mut on_account: F,
+) -> Result<MetadataScan>
+where
+    I: IntoIterator<Item = Result<MintInfo>>,
+    F: FnMut(AccountStruct) -> Result<()>,
+{
+    let mut found = 0;
+    let mut failed_mints: Vec<Pubkey> = Vec::new();
+    let progress = observer.progress("get_metadata", total);
+    for_each_in_flight(
+        mints,
+        client.in_flight(),
+        |mint_info: Result<MintInfo>| async move {
+            let mint_info = mint_info?;
+            let metadata_pubkey = get_metadata_pda(&mint_info.mint_pubkey);
+            let metadata_account = client
+                .get_account_with_commitment_async(&metadata_pubkey, commitment)
+                .await;
+            Ok((mint_info, metadata_pubkey, metadata_account))
+        },
+        |lookup: Result<_>| {
+            let (mint_info, metadata_pubkey, metadata_account) = lookup?;
+            progress.inc(1);
+            match metadata_account {
+                Ok(response) => {
+                    if let Some(account) = response.value {
+                        match MetadataInfo::try_from((
+                            metadata_pubkey,
+                            account,
+                            response.context.slot,
+                        )) {
+                            Ok(metadata_info) => {
+                                found += 1;
+                                on_account(AccountStruct::new(mint_info, metadata_info))?;
+                            }
+                            Err(e) => debug!("Skipping metadata {}: {}", metadata_pubkey, e),
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "Couldn't fetch metadata for {}: {}",
+                        mint_info.mint_pubkey, e
+                    );
+                    failed_mints.push(mint_info.mint_pubkey);
                 }
             }
-        }
-    });
-    let account_info = Arc::try_unwrap(account_info).unwrap().into_inner().unwrap();
-
-    println!(
-        "Found {} fungible accounts with metadata.",
-        account_info.len()
-    );
+            Ok(())
+        },
+    )?;
+    progress.finish();
+    report_metadata_scan(observer, found, &failed_mints);
+
+    Ok(MetadataScan {
+        found,
+        failed_mints,
+    })
+}
 
-    if !no_save {
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open("./account_info.json");
-        match file {
-            Err(_) => println!("Error opening account info file"),
-            Ok(mut f) => {
-                println!("Saving accounts info file...");
-                let start = Instant::now();
-                serde_json::to_writer(&mut f, &account_info)?;
-                let duration = start.elapsed();
-                println!(
-                    "Saved accounts info file in {} minutes and {} seconds!",
-                    duration.as_secs() / 60,
-                    duration.as_secs() % 60
-                );
+/// Same as `get_metadata_accounts`, but looks the metadata PDAs up `METADATA_BATCH_SIZE` at a
+/// time with getMultipleAccounts. A batch that fails puts all of its mints in `failed_mints`
+pub fn get_metadata_accounts_batched<F>(
+    client: &RpcPool,
+    mints: Vec<MintInfo>,
+    commitment: CommitmentConfig,
+    observer: &dyn Observer,
+    mut on_account: F,
+) -> Result<MetadataScan>
+where
+    F: FnMut(AccountStruct) -> Result<()>,
+{
+    let mut found = 0;
+    let mut failed_mints: Vec<Pubkey> = Vec::new();
+    let progress = observer.progress("get_metadata", mints.len() as u64);
+    let batches: Vec<Vec<MintInfo>> = mints
+        .chunks(METADATA_BATCH_SIZE)
+        .map(<[MintInfo]>::to_vec)
+        .collect();
+    for_each_in_flight(
+        batches,
+        client.in_flight(),
+        |batch: Vec<MintInfo>| async move {
+            let pdas: Vec<Pubkey> = batch
+                .iter()
+                .map(|mint_info| get_metadata_pda(&mint_info.mint_pubkey))
+                .collect();
+            let accounts = client
+                .get_multiple_accounts_with_commitment_async(&pdas, commitment)
+                .await;
+            (batch, pdas, accounts)
+        },
+        |(batch, pdas, accounts)| {
+            progress.inc(batch.len() as u64);
+            match accounts {
+                Ok(response) => {
+                    let slot = response.context.slot;
+                    for ((mint_info, metadata_pubkey), account) in
+                        batch.into_iter().zip(pdas).zip(response.value)
+                    {
+                        let account = match account {
+                            Some(account) => account,
+                            None => continue,
+                        };
+                        match MetadataInfo::try_from((metadata_pubkey, account, slot)) {
+                            Ok(metadata_info) => {
+                                found += 1;
+                                on_account(AccountStruct::new(mint_info, metadata_info))?;
+                            }
+                            Err(e) => debug!("Skipping metadata {}: {}", metadata_pubkey, e),
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Couldn't fetch metadata for {} mints: {}", batch.len(), e);
+                    failed_mints.extend(batch.iter().map(|mint_info| mint_info.mint_pubkey));
+                }
             }
-        }
-    };
+            Ok(())
+        },
+    )?;
+    progress.finish();
+    report_metadata_scan(observer, found, &failed_mints);
+
+    Ok(MetadataScan {
+        found,
+        failed_mints,
+    })
+}
 
-    Ok(account_info)
-}
-
-pub fn get_token_entries(
-    full_accounts: Option<Vec<AccountStruct>>,
-    no_save: bool,
-) -> Result<Vec<TokenListEntry>> {
-    let account_vec = match full_accounts {
-        Some(a) => a,
-        None => {
-            println!("Reading accounts info from file...");
-            let full_accounts_file = OpenOptions::new()
-                .write(true)
-                .read(true)
-                .open("./account_info.json")?;
-            let reader = BufReader::new(&full_accounts_file);
-            let to_return = serde_json::from_reader(reader).expect("Error parsing json file");
-            println!("Read full accounts file.");
-            to_return
-        }
-    };
-    // let account_vec = filter_accounts(account_vec).expect("Error filtering accounts");
-    println!(
-        "Total fungible mint accounts with metadata: {}",
-        account_vec.len()
-    );
-    let token_entries = get_token_entry_vec(account_vec);
-
-    if !no_save {
-        let _create_dir = fs::create_dir("./draft");
-        let start = Instant::now();
-        let mut token_list_file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open("./draft/tokenlist.json")?;
-        println!("Saving tokenlist file...");
-        serde_json::to_writer(&mut token_list_file, &token_entries)?;
-        let duration = start.elapsed();
-        println!(
-            "Saved tokenlist file in {} minutes and {} seconds!",
-            duration.as_secs() / 60,
-            duration.as_secs() % 60
-        );
+/// Turns each account into a token list entry and hands it to `on_entry`. Returns how many
+pub fn get_token_entries<I, F>(
+    accounts: I,
+    observer: &dyn Observer,
+    mut on_entry: F,
+) -> Result<usize>
+where
+    I: IntoIterator<Item = Result<AccountStruct>>,
+    F: FnMut(TokenListEntry) -> Result<()>,
+{
+    let mut count = 0;
+    for account in accounts {
+        on_entry(TokenListEntry::from(account?))?;
+        count += 1;
     }
-    Ok(token_entries)
-}
-
-pub fn parse_token_uri(
-    token_entries: Option<Vec<TokenListEntry>>,
-    no_save: bool,
-) -> Result<Vec<TokenListEntry>> {
-    let token_entries = match token_entries {
-        Some(t) => t,
-        None => {
-            println!("Reading token entries from file...");
-            let token_list_file = OpenOptions::new()
-                .write(true)
-                .read(true)
-                .open("./draft/tokenlist.json")?;
-            let reader = BufReader::new(&token_list_file);
-
-            let to_return: Vec<TokenListEntry> =
-                serde_json::from_reader(reader).expect("Error parsing token list file");
-            println!(
-                "Read token list file of {} token list entries.",
-                to_return.len()
-            );
-            to_return
-        }
-    };
+    report_token_entries(observer, count, count);
+    Ok(count)
+}
 
-    let _create_dir = fs::create_dir("./draft");
-    let mut no_uri_file = OpenOptions::new()
-        .write(true)
-        .read(true)
-        .create(true)
-        .open("./draft/no_uri_tokenlist.json")?;
-    let mut uri_file = OpenOptions::new()
-        .write(true)
-        .read(true)
-        .create(true)
-        .open("./draft/uri_tokenlist.json")?;
-
-    let uri_list: Arc<Mutex<Vec<TokenListEntry>>> = Arc::new(Mutex::new(Vec::new()));
-    let no_uri_list: Arc<Mutex<Vec<TokenListEntry>>> = Arc::new(Mutex::new(Vec::new()));
-
-    println!("Parsing token list...");
-    token_entries.par_iter().progress().for_each(|entry| {
+/// Sorts token list entries into the ones with a logo uri and the ones without
+pub fn parse_token_uri<I, F, G>(
+    token_entries: I,
+    observer: &dyn Observer,
+    mut with_uri: F,
+    mut without_uri: G,
+) -> Result<TokenListCounts>
+where
+    I: IntoIterator<Item = Result<TokenListEntry>>,
+    F: FnMut(TokenListEntry) -> Result<()>,
+    G: FnMut(TokenListEntry) -> Result<()>,
+{
+    status!(observer, "Parsing token list...");
+    let mut counts = TokenListCounts::default();
+    for entry in token_entries {
+        let entry = entry?;
         if entry.logo_uri.is_empty() {
-            let no_uri_list = no_uri_list.clone();
-            no_uri_list.lock().unwrap().push(entry.clone());
+            without_uri(entry)?;
+            counts.without_uri += 1;
         } else {
-            let uri_list = uri_list.clone();
-            uri_list.lock().unwrap().push(entry.clone());
+            with_uri(entry)?;
+            counts.with_uri += 1;
         }
-    });
+    }
+    report_token_list(observer, counts);
+    Ok(counts)
+}
 
-    let uri_list = Arc::try_unwrap(uri_list).unwrap().into_inner().unwrap();
-    let no_uri_list = Arc::try_unwrap(no_uri_list).unwrap().into_inner().unwrap();
-    println!(
-        "Found {} items with a URI and {} items with no URI.",
-        uri_list.len(),
-        no_uri_list.len()
-    );
-    if !no_save {
-        println!("Saving pretty printed parsed token list files...");
-        serde_json::to_writer_pretty(&mut uri_file, &uri_list)
-            .expect("Error writing uri token list");
-        serde_json::to_writer_pretty(&mut no_uri_file, &no_uri_list)
-            .expect("Error writing no uri token list");
-        println!("Saved parsed token list files!");
+fn report_metadata_scan(observer: &dyn Observer, found: usize, failed_mints: &[Pubkey]) {
+    status!(observer, "Found {} fungible accounts with metadata.", found);
+    // These aren't missing metadata, we just never found out. Keep them around so they can be
+    // looked at or retried instead of quietly vanishing from the token list
+    if !failed_mints.is_empty() {
+        status!(
+            observer,
+            "Couldn't fetch metadata for {} mints after retrying.",
+            failed_mints.len()
+        );
     }
+    observer.event(
+        "metadata_accounts",
+        json!({ "found": found, "failed": failed_mints.len() }),
+    );
+}
 
-    Ok(uri_list)
+fn report_token_entries(observer: &dyn Observer, accounts: usize, entries: usize) {
+    status!(
+        observer,
+        "Total fungible mint accounts with metadata: {}",
+        accounts
+    );
+    observer.event("token_list", json!({ "entries": entries }));
 }
 
-fn filter_mints(mint_accounts: Vec<(Pubkey, Account)>) -> Vec<MintInfo> {
-    let mint_vec: Arc<Mutex<Vec<MintInfo>>> = Arc::new(Mutex::new(Vec::new()));
-    mint_accounts
-        .par_iter()
-        .progress()
-        .for_each(|(mint_pubkey, mint_account)| {
-            let mint_vec = mint_vec.clone();
+fn report_token_list(observer: &dyn Observer, counts: TokenListCounts) {
+    status!(
+        observer,
+        "Found {} items with a URI and {} items with no URI.",
+        counts.with_uri,
+        counts.without_uri
+    );
+    observer.event(
+        "parsed_token_list",
+        json!({ "with_uri": counts.with_uri, "without_uri": counts.without_uri }),
+    );
+}
 
-            let mint_info = MintInfo::try_from((*mint_pubkey, mint_account.clone()));
+fn report_token_list_diff(observer: &dyn Observer, diff: &TokenListDiff) {
+    status!(
+        observer,
+        "Token list changes: {} added, {} removed, {} changed.",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len()
+    );
+    observer.event(
+        "token_list_diff",
+        json!({ "added": diff.added.len(), "removed": diff.removed.len(), "changed": diff.changed.len() }),
+    );
+}
 
-            if let Ok(mint) = mint_info {
-                if mint.is_fungible() {
-                    mint_vec.lock().unwrap().push(mint);
-                }
-            }
-        });
-    Arc::try_unwrap(mint_vec).unwrap().into_inner().unwrap()
+/// Consumes the raw accounts as it goes, so their data is freed as soon as it's decoded
+fn filter_mints(mint_accounts: Vec<(Pubkey, Account)>, slot: Slot) -> Vec<MintInfo> {
+    mint_accounts
+        .into_par_iter()
+        .filter_map(|(pubkey, account)| MintInfo::try_from((pubkey, account, slot)).ok())
+        .filter(MintInfo::is_fungible)
+        .collect()
 }
 
 // fn filter_accounts(account_vec: Vec<AccountStruct>) -> Result<Vec<AccountStruct>> {
@@ -478,23 +898,6 @@ fn filter_mints(mint_accounts: Vec<(Pubkey, Account)>) -> Vec<MintInfo> {
 //     Ok(account_vec)
 // }
 
-fn get_token_entry_vec(account_vec: Vec<AccountStruct>) -> Vec<TokenListEntry> {
-    let token_entries: Arc<Mutex<Vec<TokenListEntry>>> = Arc::new(Mutex::new(Vec::new()));
-    account_vec
-        .par_iter()
-        .progress()
-        .for_each(|account_struct| {
-            let token_entries = token_entries.clone();
-            token_entries
-                .lock()
-                .unwrap()
-                .push(TokenListEntry::from(account_struct.clone()));
-        });
-    Arc::try_unwrap(token_entries)
-        .unwrap()
-        .into_inner()
-        .unwrap()
-}
 pub fn do_stuff() -> Result<()> {
     Ok(())
 }
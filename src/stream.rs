@@ -0,0 +1,101 @@
+use crate::errors::{MetabobError, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+/// Writes one json value per line, so the file can be read back a record at a time
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+    name: String,
+    count: usize,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub fn new(writer: W, name: &str) -> NdjsonWriter<W> {
+        NdjsonWriter {
+            writer,
+            name: name.to_string(),
+            count: 0,
+        }
+    }
+
+    pub fn write<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, value).map_err(MetabobError::json(&self.name))?;
+        self.writer
+            .write_all(b"\n")
+            .map_err(MetabobError::io(&self.name))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Flushes and returns how many records were written
+    pub fn finish(mut self) -> Result<usize> {
+        self.writer.flush().map_err(MetabobError::io(&self.name))?;
+        Ok(self.count)
+    }
+}
+
+/// Writes a plain json array one element at a time, for outputs other tools read as a whole
+pub struct JsonArrayWriter<W: Write> {
+    writer: W,
+    name: String,
+    count: usize,
+}
+
+impl<W: Write> JsonArrayWriter<W> {
+    pub fn new(writer: W, name: &str) -> JsonArrayWriter<W> {
+        JsonArrayWriter {
+            writer,
+            name: name.to_string(),
+            count: 0,
+        }
+    }
+
+    pub fn write<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        let separator: &[u8] = if self.count == 0 { b"[" } else { b"," };
+        self.writer
+            .write_all(separator)
+            .map_err(MetabobError::io(&self.name))?;
+        serde_json::to_writer(&mut self.writer, value).map_err(MetabobError::json(&self.name))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Closes the array, flushes and returns how many elements were written
+    pub fn finish(mut self) -> Result<usize> {
+        let end: &[u8] = if self.count == 0 { b"[]" } else { b"]" };
+        self.writer
+            .write_all(end)
+            .and_then(|_| self.writer.flush())
+            .map_err(MetabobError::io(&self.name))?;
+        Ok(self.count)
+    }
+}
+
+/// Reads an NDJSON file lazily, one record per line. Blank lines are skipped
+pub fn read_ndjson<T: DeserializeOwned>(path: &Path) -> Result<impl Iterator<Item = Result<T>>> {
+    let file = File::open(path).map_err(MetabobError::io(path))?;
+    let path = path.to_path_buf();
+    Ok(BufReader::new(file)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(move |line| {
+            let line = line.map_err(MetabobError::io(&path))?;
+            serde_json::from_str(&line).map_err(MetabobError::json(&path))
+        }))
+}
+
+/// Number of records in an NDJSON file, for sizing progress bars before streaming it
+pub fn count_ndjson(path: &Path) -> Result<u64> {
+    let file = File::open(path).map_err(MetabobError::io(path))?;
+    let mut count = 0;
+    for line in BufReader::new(file).lines() {
+        if !line.map_err(MetabobError::io(path))?.trim().is_empty() {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,514 @@
+use crate::errors::{MetabobError, Result};
+use crate::spl::{TokenExtensions, TokenListEntry};
+use crate::stream::read_ndjson;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fs::File,
+    io::BufReader,
+    path::Path,
+    str::FromStr,
+};
+
+/// One field of a token list entry that isn't what it was
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TokenListChange {
+    #[serde(rename = "chainId")]
+    pub chain_id: u8,
+    pub address: String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// How a token list moved between two versions, each part sorted by address and chain
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TokenListDiff {
+    pub added: Vec<TokenListEntry>,
+    pub removed: Vec<TokenListEntry>,
+    pub changed: Vec<TokenListChange>,
+}
+
+impl TokenListDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    pub fn sort(&mut self) {
+        self.added
+            .sort_by(|a, b| (&a.address, a.chain_id).cmp(&(&b.address, b.chain_id)));
+        self.removed
+            .sort_by(|a, b| (&a.address, a.chain_id).cmp(&(&b.address, b.chain_id)));
+        self.changed
+            .sort_by(|a, b| (&a.address, a.chain_id).cmp(&(&b.address, b.chain_id)));
+    }
+}
+
+/// A token list field that can differ between two entries for the same address and chain
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Field {
+    #[serde(rename = "name")]
+    Name,
+    #[serde(rename = "symbol")]
+    Symbol,
+    #[serde(rename = "decimals")]
+    Decimals,
+    #[serde(rename = "logoURI")]
+    LogoUri,
+}
+
+impl Field {
+    pub const ALL: [Field; 4] = [Field::Name, Field::Symbol, Field::Decimals, Field::LogoUri];
+
+    /// The name the field has in token list json
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::Symbol => "symbol",
+            Field::Decimals => "decimals",
+            Field::LogoUri => "logoURI",
+        }
+    }
+
+    pub fn value(self, entry: &TokenListEntry) -> String {
+        match self {
+            Field::Name => entry.name.clone(),
+            Field::Symbol => entry.symbol.clone(),
+            Field::Decimals => entry.decimals.to_string(),
+            Field::LogoUri => entry.logo_uri.clone(),
+        }
+    }
+
+    fn copy(self, from: &TokenListEntry, to: &mut TokenListEntry) {
+        match self {
+            Field::Name => to.name = from.name.clone(),
+            Field::Symbol => to.symbol = from.symbol.clone(),
+            Field::Decimals => to.decimals = from.decimals,
+            Field::LogoUri => to.logo_uri = from.logo_uri.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Field {
+    type Err = MetabobError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(Field::Name),
+            "symbol" => Ok(Field::Symbol),
+            "decimals" => Ok(Field::Decimals),
+            "logouri" | "logo_uri" | "logo" => Ok(Field::LogoUri),
+            _ => Err(MetabobError::Validation(format!(
+                "Unknown token list field {}. Expected name, symbol, decimals or logoURI",
+                s
+            ))),
+        }
+    }
+}
+
+/// Every field that differs between two versions of an entry, including each key of its
+/// extensions, like `extensions.supply`
+pub fn diff_entries(old: &TokenListEntry, new: &TokenListEntry) -> Vec<FieldChange> {
+    let fields = Field::ALL
+        .iter()
+        .map(|field| (field.to_string(), field.value(old), field.value(new)));
+    let mut old_extensions = extension_values(old);
+    let mut new_extensions = extension_values(new);
+    let keys: BTreeSet<String> = old_extensions
+        .keys()
+        .chain(new_extensions.keys())
+        .cloned()
+        .collect();
+    let extensions = keys.into_iter().map(|key| {
+        let old = old_extensions.remove(&key).unwrap_or_default();
+        let new = new_extensions.remove(&key).unwrap_or_default();
+        (format!("extensions.{}", key), old, new)
+    });
+    fields
+        .chain(extensions)
+        .filter(|(_, old, new)| old != new)
+        .map(|(field, old, new)| FieldChange { field, old, new })
+        .collect()
+}
+
+/// An entry's extensions as strings by key. Strings are kept as they are and anything else
+/// is written out as json
+fn extension_values(entry: &TokenListEntry) -> BTreeMap<String, String> {
+    match entry.extensions.as_ref().map(serde_json::to_value) {
+        Some(Ok(JsonValue::Object(extensions))) => extensions
+            .into_iter()
+            .map(|(key, value)| match value {
+                JsonValue::String(value) => (key, value),
+                value => (key, value.to_string()),
+            })
+            .collect(),
+        _ => BTreeMap::new(),
+    }
+}
+
+/// Compares two token lists by address and chain
+pub fn diff_token_lists(old: Vec<TokenListEntry>, new: Vec<TokenListEntry>) -> TokenListDiff {
+    let mut old = by_key(old, "old");
+    let mut diff = TokenListDiff::default();
+    for ((address, chain_id), entry) in by_key(new, "new") {
+        match old.remove(&(address.clone(), chain_id)) {
+            Some(previous) => {
+                let changes = diff_entries(&previous, &entry);
+                if !changes.is_empty() {
+                    diff.changed.push(TokenListChange {
+                        chain_id,
+                        address,
+                        changes,
+                    });
+                }
+            }
+            None => diff.added.push(entry),
+        }
+    }
+    diff.removed = old.into_values().collect();
+    diff.sort();
+    diff
+}
+
+/// Which list a merged field came from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Curated,
+    Chain,
+}
+
+impl FromStr for Source {
+    type Err = MetabobError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "curated" => Ok(Source::Curated),
+            "chain" | "generated" => Ok(Source::Chain),
+            _ => Err(MetabobError::Validation(format!(
+                "Unknown token list source {}. Expected curated or chain",
+                s
+            ))),
+        }
+    }
+}
+
+/// `FIELD=SOURCE`, e.g. `decimals=curated`
+#[derive(Debug, Clone, Copy)]
+pub struct FieldPreference {
+    pub field: Field,
+    pub source: Source,
+}
+
+impl FromStr for FieldPreference {
+    type Err = MetabobError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (field, source) = s.split_once('=').ok_or_else(|| {
+            MetabobError::Validation(format!("Expected FIELD=curated|chain, got {}", s))
+        })?;
+        Ok(FieldPreference {
+            field: field.trim().parse()?,
+            source: source.trim().parse()?,
+        })
+    }
+}
+
+/// Which list wins each field for addresses that are in both. The curated list wins
+/// everything but decimals by default, since decimals can't be wrong on chain
+#[derive(Debug, Clone)]
+pub struct MergeRules {
+    winners: BTreeMap<Field, Source>,
+}
+
+impl Default for MergeRules {
+    fn default() -> Self {
+        MergeRules {
+            winners: Field::ALL
+                .iter()
+                .map(|field| {
+                    let source = match field {
+                        Field::Decimals => Source::Chain,
+                        _ => Source::Curated,
+                    };
+                    (*field, source)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl MergeRules {
+    pub fn prefer(mut self, preference: FieldPreference) -> MergeRules {
+        self.winners.insert(preference.field, preference.source);
+        self
+    }
+
+    pub fn winner(&self, field: Field) -> Source {
+        self.winners[&field]
+    }
+}
+
+/// A field the two lists disagreed on, and which one won
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MergeConflict {
+    #[serde(rename = "chainId")]
+    pub chain_id: u8,
+    pub address: String,
+    pub field: Field,
+    pub curated: String,
+    pub chain: String,
+    pub winner: Source,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MergeReport {
+    /// The merged list, sorted by address and chain
+    pub merged: Vec<TokenListEntry>,
+    pub curated_only: usize,
+    pub chain_only: usize,
+    pub both: usize,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// Merges the chain generated list into the curated one by address and chain, since one
+/// address can be listed for mainnet, testnet and devnet. Entries only one list has
+/// are kept as they are. For the rest each field comes from the winner under `rules`, unless
+/// the winner left it empty, and the supply and authority extensions always come from chain.
+/// Any other keys, like tags, are kept from both, the curated list's winning
+pub fn merge_token_lists(
+    curated: Vec<TokenListEntry>,
+    chain: Vec<TokenListEntry>,
+    rules: &MergeRules,
+) -> MergeReport {
+    let mut merged = by_key(curated, "curated");
+    let mut report = MergeReport {
+        curated_only: merged.len(),
+        ..MergeReport::default()
+    };
+    for (key, chain_entry) in by_key(chain, "chain") {
+        let entry = match merged.get_mut(&key) {
+            Some(entry) => entry,
+            None => {
+                report.chain_only += 1;
+                merged.insert(key, chain_entry);
+                continue;
+            }
+        };
+        report.curated_only -= 1;
+        report.both += 1;
+        if let Some(chain_extensions) = &chain_entry.extensions {
+            entry
+                .extensions
+                .get_or_insert_with(TokenExtensions::default)
+                .update_mint(chain_extensions);
+        }
+        for (key, value) in &chain_entry.other {
+            entry
+                .other
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+        for field in Field::ALL {
+            let curated_value = field.value(entry);
+            let chain_value = field.value(&chain_entry);
+            if curated_value == chain_value {
+                continue;
+            }
+            let winner = match (rules.winner(field), chain_value.is_empty()) {
+                (Source::Chain, false) => Source::Chain,
+                (Source::Curated, _) if curated_value.is_empty() => Source::Chain,
+                _ => Source::Curated,
+            };
+            if winner == Source::Chain {
+                field.copy(&chain_entry, entry);
+            }
+            if !curated_value.is_empty() && !chain_value.is_empty() {
+                report.conflicts.push(MergeConflict {
+                    chain_id: key.1,
+                    address: key.0.clone(),
+                    field,
+                    curated: curated_value,
+                    chain: chain_value,
+                    winner,
+                });
+            }
+        }
+    }
+    report.merged = merged.into_values().collect();
+    report
+}
+
+/// A token list file in either shape we read, so it can be written back the same way
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum TokenListFile {
+    /// A plain json array, or NDJSON
+    Entries(Vec<TokenListEntry>),
+    /// A standard `{"tokens": [...]}` token list. `info` is the rest of it: its name, logoURI,
+    /// keywords, tags, timestamp, version and so on
+    TokenList {
+        #[serde(flatten)]
+        info: BTreeMap<String, JsonValue>,
+        tokens: Vec<TokenListEntry>,
+    },
+}
+
+impl TokenListFile {
+    /// Reads a json array, a `{"tokens": [...]}` token list, or an NDJSON file going by the
+    /// `.ndjson` extension
+    pub fn read(path: &Path) -> Result<TokenListFile> {
+        if path.extension().and_then(|ext| ext.to_str()) == Some("ndjson") {
+            return Ok(TokenListFile::Entries(
+                read_ndjson(path)?.collect::<Result<_>>()?,
+            ));
+        }
+        let file = File::open(path).map_err(MetabobError::io(path))?;
+        serde_json::from_reader(BufReader::new(file)).map_err(MetabobError::json(path))
+    }
+
+    pub fn tokens(&self) -> &[TokenListEntry] {
+        match self {
+            TokenListFile::Entries(tokens) | TokenListFile::TokenList { tokens, .. } => tokens,
+        }
+    }
+
+    pub fn into_tokens(self) -> Vec<TokenListEntry> {
+        match self {
+            TokenListFile::Entries(tokens) | TokenListFile::TokenList { tokens, .. } => tokens,
+        }
+    }
+
+    /// The same file with its tokens swapped for `tokens`
+    pub fn with_tokens(self, tokens: Vec<TokenListEntry>) -> TokenListFile {
+        match self {
+            TokenListFile::Entries(_) => TokenListFile::Entries(tokens),
+            TokenListFile::TokenList { info, .. } => TokenListFile::TokenList { info, tokens },
+        }
+    }
+}
+
+/// Reads just the entries of any token list file `TokenListFile::read` takes
+pub fn read_token_list(path: &Path) -> Result<Vec<TokenListEntry>> {
+    TokenListFile::read(path).map(TokenListFile::into_tokens)
+}
+
+/// What identifies an entry. The same address can be listed once per chain
+fn key(entry: &TokenListEntry) -> (String, u8) {
+    (entry.address.clone(), entry.chain_id)
+}
+
+fn by_key(entries: Vec<TokenListEntry>, list: &str) -> BTreeMap<(String, u8), TokenListEntry> {
+    let mut by_key = BTreeMap::new();
+    for entry in entries {
+        if let Some(duplicate) = by_key.insert(key(&entry), entry) {
+            warn!(
+                "{} is in the {} list more than once for chain {}, keeping the last one",
+                duplicate.address, list, duplicate.chain_id
+            );
+        }
+    }
+    by_key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(chain_id: u8, address: &str, name: &str) -> TokenListEntry {
+        TokenListEntry {
+            chain_id,
+            ..TokenListEntry::new(
+                address.to_string(),
+                String::from("TKN"),
+                name.to_string(),
+                6,
+                String::new(),
+            )
+        }
+    }
+
+    #[test]
+    fn merge_keeps_one_entry_per_chain() {
+        let curated = vec![
+            entry(101, "A", "Mainnet"),
+            entry(102, "A", "Testnet"),
+            entry(103, "A", "Devnet"),
+        ];
+        let chain = vec![entry(101, "A", "")];
+        let report = merge_token_lists(curated, chain, &MergeRules::default());
+
+        let names: Vec<_> = report
+            .merged
+            .iter()
+            .map(|entry| (entry.chain_id, entry.name.as_str()))
+            .collect();
+        assert_eq!(names, [(101, "Mainnet"), (102, "Testnet"), (103, "Devnet")]);
+        assert_eq!(
+            (report.both, report.curated_only, report.chain_only),
+            (1, 2, 0)
+        );
+    }
+
+    #[test]
+    fn diff_matches_entries_by_chain() {
+        let old = vec![entry(101, "A", "Mainnet"), entry(103, "A", "Devnet")];
+        let new = vec![entry(101, "A", "Mainnet"), entry(103, "A", "Renamed")];
+        let diff = diff_token_lists(old, new);
+
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].chain_id, 103);
+        assert_eq!(
+            diff.changed[0].changes,
+            [FieldChange {
+                field: String::from("name"),
+                old: String::from("Devnet"),
+                new: String::from("Renamed"),
+            }]
+        );
+    }
+
+    #[test]
+    fn diff_includes_extensions() {
+        let with_extensions = |supply: &str, mint_authority: Option<&str>| TokenListEntry {
+            extensions: Some(TokenExtensions {
+                supply: Some(supply.to_string()),
+                mint_authority: mint_authority.map(String::from),
+                ..TokenExtensions::default()
+            }),
+            ..entry(101, "A", "Token")
+        };
+        let changes = diff_entries(
+            &with_extensions("100", Some("Auth")),
+            &with_extensions("250", None),
+        );
+        let change = |field: &str, old: &str, new: &str| FieldChange {
+            field: field.to_string(),
+            old: old.to_string(),
+            new: new.to_string(),
+        };
+        assert_eq!(
+            changes,
+            [
+                change("extensions.mintAuthority", "Auth", ""),
+                change("extensions.supply", "100", "250"),
+            ]
+        );
+        assert!(diff_entries(&entry(101, "A", "Token"), &entry(101, "A", "Token")).is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+use image::{ImageBuffer, ImageOutputFormat, Rgba};
+use metabob::logos::{check_logos, thumbnail_path, LogoCheck, LogoOptions, LogoStatus};
+use metabob::observer::Silent;
+use metabob::spl::TokenListEntry;
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Cursor, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+const MAX_BYTES: u64 = 4096;
+
+struct Fixture {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+    /// Leave out Content-Length and just close the connection after the body
+    no_length: bool,
+}
+
+fn fixture(content_type: &'static str, body: Vec<u8>) -> Fixture {
+    Fixture {
+        status: "200 OK",
+        content_type,
+        body,
+        no_length: false,
+    }
+}
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let image = ImageBuffer::from_pixel(width, height, Rgba([200u8, 30, 30, 255]));
+    let mut bytes = Cursor::new(Vec::new());
+    image.write_to(&mut bytes, ImageOutputFormat::Png).unwrap();
+    bytes.into_inner()
+}
+
+/// A tiny HTTP/1.0 server with a fixed response per path, good for one request per connection
+fn serve(routes: HashMap<&'static str, Fixture>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            respond(stream, &routes);
+        }
+    });
+    addr
+}
+
+fn respond(mut stream: TcpStream, routes: &HashMap<&'static str, Fixture>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).unwrap() == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let not_found = Fixture {
+        status: "404 Not Found",
+        content_type: "text/plain",
+        body: b"not found".to_vec(),
+        no_length: false,
+    };
+    let fixture = routes.get(path).unwrap_or(&not_found);
+    let mut head = format!(
+        "HTTP/1.0 {}\r\nContent-Type: {}\r\nConnection: close\r\n",
+        fixture.status, fixture.content_type
+    );
+    if !fixture.no_length {
+        head.push_str(&format!("Content-Length: {}\r\n", fixture.body.len()));
+    }
+    head.push_str("\r\n");
+    // The client hangs up early on oversized logos, so write errors are expected
+    let _ = stream
+        .write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(&fixture.body));
+}
+
+/// An entry for a new mint, with `symbol` to tell its check apart by
+fn entry(symbol: &str, logo_uri: String) -> TokenListEntry {
+    TokenListEntry::new(
+        Pubkey::new_unique().to_string(),
+        symbol.to_string(),
+        symbol.to_uppercase(),
+        6,
+        logo_uri,
+    )
+}
+
+fn thumbnail(dir: &std::path::Path, entry: &TokenListEntry) -> PathBuf {
+    thumbnail_path(dir, &entry.address.parse().unwrap(), entry.chain_id)
+}
+
+fn run(entries: Vec<TokenListEntry>, thumbnail_dir: Option<PathBuf>) -> HashMap<String, LogoCheck> {
+    let options = LogoOptions {
+        max_bytes: MAX_BYTES,
+        timeout: Duration::from_secs(10),
+        in_flight: 4,
+        thumbnail_dir,
+        thumbnail_size: 16,
+    };
+    let mut checks = HashMap::new();
+    let counts = check_logos(entries, &options, &Silent, |check| {
+        checks.insert(check.symbol.clone(), check);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(counts.checked, checks.len());
+    checks
+}
+
+#[test]
+fn checks_logos_against_a_local_server() {
+    let mut routes = HashMap::new();
+    routes.insert("/logo.png", fixture("image/png", png(40, 20)));
+    routes.insert(
+        "/logo.svg",
+        fixture(
+            "image/svg+xml",
+            b"<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>".to_vec(),
+        ),
+    );
+    routes.insert(
+        "/big.png",
+        fixture("image/png", vec![0; MAX_BYTES as usize * 2]),
+    );
+    routes.insert(
+        "/unsized.png",
+        Fixture {
+            no_length: true,
+            ..fixture("image/png", vec![0; MAX_BYTES as usize * 2])
+        },
+    );
+    routes.insert(
+        "/notes.txt",
+        fixture("text/plain", b"just some text".to_vec()),
+    );
+    let mut truncated = png(40, 20);
+    truncated.truncate(truncated.len() / 2);
+    routes.insert("/truncated.png", fixture("image/png", truncated));
+    let addr = serve(routes);
+    let url = |path: &str| format!("http://{}{}", addr, path);
+
+    let thumbnails = std::env::temp_dir().join(format!("metabob-logos-{}", std::process::id()));
+    let png = entry("png", url("/logo.png"));
+    let truncated = entry("truncated", url("/truncated.png"));
+    let bad_address = TokenListEntry {
+        address: String::from("../escaped"),
+        ..entry("bad_address", url("/logo.png"))
+    };
+    let checks = run(
+        vec![
+            png.clone(),
+            entry("svg", url("/logo.svg")),
+            entry("big", url("/big.png")),
+            entry("unsized", url("/unsized.png")),
+            entry("text", url("/notes.txt")),
+            truncated.clone(),
+            entry("missing", url("/missing.png")),
+            entry("nothing", String::new()),
+            bad_address,
+        ],
+        Some(thumbnails.clone()),
+    );
+
+    assert_eq!(checks.len(), 8, "entries without a logo are skipped");
+
+    let png_check = &checks["png"];
+    assert_eq!(png_check.status, LogoStatus::Ok);
+    assert_eq!(png_check.content_type.as_deref(), Some("image/png"));
+    assert_eq!(png_check.format.as_deref(), Some("png"));
+    assert_eq!((png_check.width, png_check.height), (Some(40), Some(20)));
+    assert_eq!(png_check.sha256.as_ref().map(String::len), Some(64));
+    let png_thumbnail = thumbnail(&thumbnails, &png);
+    assert_eq!(png_check.thumbnail.as_ref(), Some(&png_thumbnail));
+    let (width, height) = image::image_dimensions(&png_thumbnail).unwrap();
+    assert_eq!((width, height), (16, 8));
+
+    let svg_check = &checks["svg"];
+    assert_eq!(svg_check.status, LogoStatus::Ok);
+    assert_eq!(svg_check.format.as_deref(), Some("svg"));
+    assert_eq!(svg_check.width, None);
+    assert!(svg_check.thumbnail.is_none());
+
+    assert_eq!(checks["big"].status, LogoStatus::Oversized);
+    assert_eq!(checks["big"].bytes, Some(MAX_BYTES * 2));
+    assert_eq!(checks["unsized"].status, LogoStatus::Oversized);
+    assert_eq!(checks["text"].status, LogoStatus::NotAnImage);
+    assert_eq!(checks["truncated"].status, LogoStatus::NotAnImage);
+    assert!(!thumbnail(&thumbnails, &truncated).exists());
+    assert_eq!(checks["missing"].status, LogoStatus::Unreachable);
+    assert_eq!(
+        checks["missing"].error.as_deref(),
+        Some("HTTP 404 Not Found")
+    );
+
+    let bad_address = &checks["bad_address"];
+    assert_eq!(bad_address.status, LogoStatus::InvalidAddress);
+    assert!(bad_address.thumbnail.is_none());
+    assert!(bad_address.sha256.is_none(), "it isn't downloaded");
+    assert_eq!(fs::read_dir(&thumbnails).unwrap().count(), 1);
+
+    fs::remove_dir_all(&thumbnails).unwrap();
+}
+
+#[test]
+fn keeps_a_thumbnail_per_chain() {
+    let mut routes = HashMap::new();
+    routes.insert("/wide.png", fixture("image/png", png(40, 20)));
+    routes.insert("/tall.png", fixture("image/png", png(20, 40)));
+    let addr = serve(routes);
+
+    let mainnet = entry("mainnet", format!("http://{}/wide.png", addr));
+    // Same mint, listed again for devnet with a different logo
+    let devnet = TokenListEntry {
+        address: mainnet.address.clone(),
+        chain_id: 103,
+        ..entry("devnet", format!("http://{}/tall.png", addr))
+    };
+    let thumbnails =
+        std::env::temp_dir().join(format!("metabob-logo-chains-{}", std::process::id()));
+    let checks = run(
+        vec![mainnet.clone(), devnet.clone()],
+        Some(thumbnails.clone()),
+    );
+
+    assert_eq!(checks["mainnet"].chain_id, 101);
+    assert_eq!(checks["devnet"].chain_id, 103);
+    let (mainnet_thumbnail, devnet_thumbnail) = (
+        thumbnail(&thumbnails, &mainnet),
+        thumbnail(&thumbnails, &devnet),
+    );
+    assert_ne!(mainnet_thumbnail, devnet_thumbnail);
+    assert_eq!(
+        image::image_dimensions(&mainnet_thumbnail).unwrap(),
+        (16, 8)
+    );
+    assert_eq!(image::image_dimensions(&devnet_thumbnail).unwrap(), (8, 16));
+
+    fs::remove_dir_all(&thumbnails).unwrap();
+}
+
+#[test]
+fn flags_a_server_that_is_not_there() {
+    // Bind and drop a listener to get a port nothing is on
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let checks = run(
+        vec![entry("gone", format!("http://{}/logo.png", addr))],
+        None,
+    );
+    assert_eq!(checks["gone"].status, LogoStatus::Unreachable);
+    assert!(checks["gone"].error.is_some());
+}